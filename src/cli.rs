@@ -0,0 +1,471 @@
+use std::{collections::BTreeSet, io::Write, process::ExitCode};
+
+use chrono::Local;
+use crop::Rope;
+use eyre::Context;
+
+use crate::{
+    cli::json::TaskJson,
+    config::Config,
+    filter::{FilteredData, TaskID, parse_date},
+    hooks::Hooks,
+    report::{self, Group, Report},
+    storage::{BoxState, Data, Task},
+};
+
+mod json;
+
+pub const USAGE: &str = "\
+Commands:
+\tadd TITLE [--tag TAG]...: add a task
+\tlist [--filter EXPR] [--json | --jsonl]: print the path and title of
+\t\tmatching tasks, or all of their fields as a JSON array or one object per
+\t\tline
+\tdone TASK: complete a task
+\tbox start|check TASK: start the next box, or check the started one
+\ttag TASK [+TAG | -TAG]...: add and remove tags
+\treport [--by tag|task|day|week|month] [--from DATE] [--to DATE]
+\t\t[--filter EXPR] [--csv | --markdown]: sum boxes checked from the --from
+\t\tDATE up to, but not on, the --to DATE, and the minutes worked on them, per
+\t\ttag by default
+
+TASK is a title, or the path of the task file, either as printed by list or
+relative to the data folder.
+
+Exit codes: 0 on success, 1 if the command failed, 2 for invalid arguments,
+3 if no single task matched.";
+
+enum Error {
+    /// The arguments couldn't be understood.
+    Usage(String),
+    /// No single task matched.
+    NotFound(String),
+    Failed(eyre::Report),
+}
+
+impl From<eyre::Report> for Error {
+    fn from(e: eyre::Report) -> Self {
+        Error::Failed(e)
+    }
+}
+
+/// Runs `command` without the tui. Errors are printed to stderr.
+pub fn run(config: &Config, command: &str, args: &[String]) -> ExitCode {
+    run_to(config, command, args, &mut std::io::stdout().lock())
+}
+
+/// Like `run`, writing output to `out`.
+fn run_to(config: &Config, command: &str, args: &[String], out: &mut impl Write) -> ExitCode {
+    let result = match command {
+        "add" => add(config, args, out),
+        "list" => list(config, args, out),
+        "done" => done(config, args),
+        "box" => step_box(config, args),
+        "tag" => tag(config, args),
+        "report" => report(config, args, out),
+        _ => Err(Error::Usage(format!("unknown command '{command}'"))),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Error::Usage(message)) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(Error::NotFound(message)) => {
+            eprintln!("{message}");
+            ExitCode::from(3)
+        }
+        Err(Error::Failed(e)) => {
+            eprintln!("{e:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn load(config: &Config) -> FilteredData {
+    let mut data = match Data::load(config.data_dir()) {
+        Ok(d) => d,
+        Err((d, e)) => {
+            // Tasks that did load can still be used.
+            eprintln!("{:?}", e.wrap_err("Error loading data"));
+            d
+        }
+    };
+    data.set_backup_count(config.backup_count);
+    FilteredData::new(data)
+}
+
+/// Writes changed tasks and runs the hooks of the changes.
+fn save(config: &Config, data: &mut FilteredData) -> Result<(), Error> {
+    let written = data.write_dirty();
+    let mut hooks = Hooks::new(config.hooks.clone());
+    for event in data.take_events() {
+        let (Some(task), Some(path)) = (data.get(event.id), data.task_path(event.id)) else {
+            continue;
+        };
+        hooks.run(&event, task, &path);
+    }
+    // The change itself was saved, so failed hooks don't fail the command.
+    for e in hooks.wait() {
+        eprintln!("{e:?}");
+    }
+    Ok(written?)
+}
+
+/// Finds the task titled `query`, or else stored at the path `query`.
+fn find(config: &Config, data: &FilteredData, query: &str) -> Result<TaskID, Error> {
+    let titled: Vec<_> = data
+        .ids()
+        .filter(|id| data.get(*id).is_some_and(|t| t.title() == query))
+        .collect();
+    match titled[..] {
+        [id] => return Ok(id),
+        [] => {}
+        _ => {
+            return Err(Error::NotFound(format!(
+                "{} tasks are titled '{query}', use a path from `list` instead",
+                titled.len()
+            )));
+        }
+    }
+    let path = config.data_dir().join(query);
+    data.ids()
+        .find(|id| data.task_path(*id).is_some_and(|p| p == path))
+        .ok_or_else(|| Error::NotFound(format!("no task is titled or stored at '{query}'")))
+}
+
+fn add(config: &Config, args: &[String], out: &mut impl Write) -> Result<(), Error> {
+    let mut title = None;
+    let mut tags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tag" => tags.push(
+                args.next()
+                    .ok_or_else(|| Error::Usage("--tag needs a value".to_string()))?
+                    .clone(),
+            ),
+            _ if title.is_none() => title = Some(arg.clone()),
+            _ => return Err(Error::Usage(format!("unexpected argument '{arg}'"))),
+        }
+    }
+    let title = title.ok_or_else(|| Error::Usage("add needs a title".to_string()))?;
+    let mut data = load(config);
+    let task = Task::new(
+        title,
+        Local::now().naive_local(),
+        vec![],
+        tags.into_iter().collect(),
+        Rope::new(),
+        None,
+    );
+    let id = data.push(task);
+    save(config, &mut data)?;
+    // Known once written, as it may have been changed to not replace a file.
+    if let Some(path) = data.task_path(id) {
+        writeln!(out, "{}", path.display()).wrap_err("writing output")?;
+    }
+    Ok(())
+}
+
+enum Format {
+    /// Path and title separated by a tab.
+    Text,
+    Json,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+fn list(config: &Config, args: &[String], out: &mut impl Write) -> Result<(), Error> {
+    let mut filter = None;
+    let mut format = Format::Text;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => {
+                filter = Some(
+                    args.next()
+                        .ok_or_else(|| Error::Usage("--filter needs a value".to_string()))?,
+                )
+            }
+            "--json" => format = Format::Json,
+            "--jsonl" => format = Format::JsonLines,
+            _ => return Err(Error::Usage(format!("unexpected argument '{arg}'"))),
+        }
+    }
+    let mut data = load(config);
+    if let Some(filter) = filter {
+        data.set_filter(filter)
+            .wrap_err("Error parsing filter")
+            .map_err(|e| Error::Usage(format!("{e:?}")))?;
+    }
+    let tasks = data.ids().map(|id| {
+        let task = data.get(id).expect("listed tasks exist");
+        let path = data.task_path(id).expect("listed tasks exist");
+        (task, path)
+    });
+    match format {
+        Format::Text => {
+            for (task, path) in tasks {
+                writeln!(out, "{}\t{}", path.display(), task.title()).wrap_err("writing output")?;
+            }
+        }
+        Format::Json => {
+            let tasks: Vec<_> = tasks
+                .map(|(task, path)| TaskJson::new(task, &path))
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &tasks).wrap_err("writing output")?;
+            writeln!(out).wrap_err("writing output")?;
+        }
+        Format::JsonLines => {
+            for (task, path) in tasks {
+                serde_json::to_writer(&mut *out, &TaskJson::new(task, &path))
+                    .wrap_err("writing output")?;
+                writeln!(out).wrap_err("writing output")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn done(config: &Config, args: &[String]) -> Result<(), Error> {
+    let [query] = args else {
+        return Err(Error::Usage("expected done TASK".to_string()));
+    };
+    let mut data = load(config);
+    let id = find(config, &data, query)?;
+    // Keep when it was first completed.
+    if data.get(id).is_some_and(|t| t.completed().is_none()) {
+        data.set_completed(id, Some(Local::now().naive_local()));
+    }
+    save(config, &mut data)
+}
+
+fn step_box(config: &Config, args: &[String]) -> Result<(), Error> {
+    let [action, query] = args else {
+        return Err(Error::Usage("expected box start|check TASK".to_string()));
+    };
+    let mut data = load(config);
+    let id = find(config, &data, query)?;
+    let task = data.get(id).expect("found tasks exist");
+    let now = Local::now().naive_local();
+    match action.as_str() {
+        "start" => {
+            if task
+                .boxes()
+                .iter()
+                .any(|b| matches!(b, BoxState::Started(_)))
+            {
+                return Err(eyre::eyre!("'{}' already has a started box", task.title()).into());
+            }
+            if !task.boxes().contains(&BoxState::Empty) {
+                data.push_box(id);
+            }
+            data.step_box_state(id, now);
+        }
+        "check" => {
+            if !data.check_started(id, now) {
+                let title = data.get(id).expect("found tasks exist").title();
+                return Err(eyre::eyre!("'{title}' has no started box").into());
+            }
+        }
+        _ => return Err(Error::Usage(format!("unknown box action '{action}'"))),
+    }
+    save(config, &mut data)
+}
+
+fn tag(config: &Config, args: &[String]) -> Result<(), Error> {
+    let [query, changes @ ..] = args else {
+        return Err(Error::Usage(
+            "expected tag TASK [+TAG | -TAG]...".to_string(),
+        ));
+    };
+    let mut data = load(config);
+    let id = find(config, &data, query)?;
+    let mut tags: BTreeSet<_> = data
+        .get(id)
+        .expect("found tasks exist")
+        .tags()
+        .iter()
+        .cloned()
+        .collect();
+    for change in changes {
+        if let Some(tag) = change.strip_prefix('+') {
+            tags.insert(tag.to_string());
+        } else if let Some(tag) = change.strip_prefix('-') {
+            tags.remove(tag);
+        } else {
+            return Err(Error::Usage(format!(
+                "expected +TAG or -TAG, found '{change}'"
+            )));
+        }
+    }
+    data.set_tags(id, tags.into_iter().collect());
+    save(config, &mut data)
+}
+
+fn report(config: &Config, args: &[String], out: &mut impl Write) -> Result<(), Error> {
+    let mut group = Group::Tag;
+    let mut from = None;
+    let mut to = None;
+    let mut filter = None;
+    let mut format = report::Format::Table;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| Error::Usage(format!("{arg} needs a value")))
+        };
+        let date = |text: &str| {
+            parse_date(text).map_err(|e| {
+                Error::Usage(format!("{:?}", e.wrap_err(format!("Error parsing {arg}"))))
+            })
+        };
+        match arg.as_str() {
+            "--by" => group = value()?.parse().map_err(|e| Error::Usage(format!("{e}")))?,
+            "--from" => from = Some(date(value()?)?),
+            "--to" => to = Some(date(value()?)?),
+            "--filter" => filter = Some(value()?),
+            "--csv" => format = report::Format::Csv,
+            "--markdown" => format = report::Format::Markdown,
+            _ => return Err(Error::Usage(format!("unexpected argument '{arg}'"))),
+        }
+    }
+    let mut data = load(config);
+    if let Some(filter) = filter {
+        data.set_filter(filter)
+            .wrap_err("Error parsing filter")
+            .map_err(|e| Error::Usage(format!("{e:?}")))?;
+    }
+    write!(
+        out,
+        "{}",
+        Report::new(&data, group, from, to).render(format)
+    )
+    .wrap_err("writing output")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::*;
+
+    fn config(dir: &Path) -> Config {
+        Config {
+            data_path: dir.to_path_buf(),
+            log_path: dir.join("log"),
+            backup_count: 1,
+            pomodoro_minutes: 25,
+            break_minutes: 5,
+            auto_check: false,
+            hooks: HashMap::new(),
+            keybinds: HashMap::new(),
+        }
+    }
+
+    /// The exit code and output of `tasks args...`.
+    fn tasks(config: &Config, args: &[&str]) -> (ExitCode, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut out = Vec::new();
+        let code = run_to(config, &args[0], &args[1..], &mut out);
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn added_paths_stay_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        let (code, first) = tasks(&config, &["add", "foo"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        let (_, second) = tasks(&config, &["add", "foo"]);
+        let (first, second) = (first.trim(), second.trim());
+        assert_ne!(first, second);
+        let (_, listed) = tasks(&config, &["list"]);
+        let mut paths: Vec<_> = listed
+            .lines()
+            .map(|l| l.split('\t').next().unwrap())
+            .collect();
+        paths.sort();
+        let mut added = [first, second];
+        added.sort();
+        assert_eq!(paths, added);
+        assert_eq!(tasks(&config, &["done", first]).0, ExitCode::SUCCESS);
+        assert_eq!(tasks(&config, &["done", second]).0, ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn tasks_are_found_by_title_or_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        tasks(&config, &["add", "foo"]);
+        tasks(&config, &["add", "foo"]);
+        let (_, bar) = tasks(&config, &["add", "bar"]);
+        let data = load(&config);
+        let bar_id = find(&config, &data, "bar").ok().unwrap();
+        assert_eq!(data.task_path(bar_id).unwrap(), Path::new(bar.trim()));
+        let relative = Path::new(bar.trim()).strip_prefix(dir.path()).unwrap();
+        let by_path = find(&config, &data, relative.to_str().unwrap()).ok();
+        assert_eq!(by_path, Some(bar_id));
+        assert_eq!(find(&config, &data, bar.trim()).ok(), Some(bar_id));
+        assert!(matches!(
+            find(&config, &data, "foo"),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            find(&config, &data, "baz"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn exit_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        tasks(&config, &["add", "foo"]);
+        tasks(&config, &["add", "foo"]);
+        tasks(&config, &["add", "bar"]);
+        for (args, code) in [
+            (&["tag", "bar", "+x"][..], 0),
+            (&["box", "start", "bar"], 0),
+            (&["box", "start", "bar"], 1),
+            (&["box", "check", "bar"], 0),
+            (&["box", "check", "bar"], 1),
+            (&["done"], 2),
+            (&["tag", "bar", "x"], 2),
+            (&["list", "--filter", "created >"], 2),
+            (&["report", "--by", "year"], 2),
+            (&["nope"], 2),
+            (&["done", "foo"], 3),
+            (&["done", "baz"], 3),
+        ] {
+            assert_eq!(tasks(&config, args).0, ExitCode::from(code), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn list_jsonl_has_an_object_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        tasks(&config, &["add", "foo", "--tag", "b", "--tag", "a"]);
+        tasks(&config, &["add", "bar"]);
+        tasks(&config, &["box", "start", "bar"]);
+        let (code, out) = tasks(&config, &["list", "--filter", "tag(a)", "--jsonl"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["title"], "foo");
+        assert_eq!(lines[0]["tags"], serde_json::json!(["a", "b"]));
+        let (_, out) = tasks(
+            &config,
+            &["list", "--jsonl", "--filter", "title contains \"bar\""],
+        );
+        let bar: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+        assert_eq!(bar["boxes"][0]["state"], "started");
+        assert!(bar["path"].as_str().unwrap().ends_with(".md"));
+    }
+}
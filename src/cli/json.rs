@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use serde::{Serialize, Serializer};
+
+use crate::storage::{BoxState, Node, Task, format_date};
+
+/// A task as printed by `list --json`. Dates are written as in task files.
+#[derive(Serialize)]
+pub struct TaskJson<'a> {
+    title: &'a str,
+    path: String,
+    created: String,
+    completed: Option<String>,
+    boxes: Vec<BoxJson<'a>>,
+    tags: Vec<&'a str>,
+    extra_fields: ExtraFields<'a>,
+}
+
+#[derive(Serialize)]
+struct BoxJson<'a> {
+    state: &'static str,
+    start: Option<String>,
+    end: Option<String>,
+    reason: Option<&'a str>,
+}
+
+/// Serialized as a map in file order.
+struct ExtraFields<'a>(Vec<(&'a str, Node)>);
+
+impl<'a> TaskJson<'a> {
+    pub fn new(task: &'a Task, path: &Path) -> Self {
+        let mut tags: Vec<_> = task.tags().iter().map(String::as_str).collect();
+        tags.sort();
+        Self {
+            title: task.title(),
+            path: path.to_string_lossy().into_owned(),
+            created: format_date(task.created()),
+            completed: task.completed().as_ref().map(format_date),
+            boxes: task.boxes().iter().map(BoxJson::new).collect(),
+            tags,
+            extra_fields: ExtraFields(task.extra_fields().collect()),
+        }
+    }
+}
+
+impl<'a> BoxJson<'a> {
+    fn new(state: &'a BoxState) -> Self {
+        let (name, start, end, reason) = match state {
+            BoxState::Checked { start, end } => ("checked", *start, Some(*end), None),
+            BoxState::Started(start) => ("started", *start, None, None),
+            BoxState::Voided { start, end, reason } => {
+                ("voided", *start, Some(*end), reason.as_deref())
+            }
+            BoxState::Empty => ("empty", None, None, None),
+        };
+        Self {
+            state: name,
+            start: start.as_ref().map(format_date),
+            end: end.as_ref().map(format_date),
+            reason,
+        }
+    }
+}
+
+impl Serialize for ExtraFields<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, node)| (key, node)))
+    }
+}
@@ -1,3 +1,6 @@
+// Loading returns the fallback config alongside its error.
+#![allow(clippy::result_large_err)]
+
 use std::{collections::HashMap, fs::OpenOptions, io::Read, path::PathBuf};
 
 use crossterm::event::KeyCode;
@@ -5,12 +8,23 @@ use eyre::{Context, OptionExt, Result};
 use serde::{Deserialize, Serialize};
 use toml::de::ValueDeserializer;
 
-use crate::tui::app::{KeyAction, Mode};
+use crate::{
+    storage::EventKind,
+    tui::app::{KeyAction, Mode},
+};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub data_path: PathBuf,
     pub log_path: PathBuf,
+    /// Number of previous versions kept next to each task file.
+    pub backup_count: usize,
+    pub pomodoro_minutes: u32,
+    pub break_minutes: u32,
+    /// Check the running box when its pomodoro ends.
+    pub auto_check: bool,
+    /// Shell command run for each kind of event.
+    pub hooks: HashMap<EventKind, String>,
     pub keybinds: HashMap<Mode, HashMap<KeyCode, KeyAction>>,
 }
 
@@ -18,15 +32,40 @@ impl Config {
     pub fn load() -> Result<Self, (Self, eyre::Report)> {
         FileConfig::load()
     }
+
+    /// `data_path` with `~` expanded.
+    pub fn data_dir(&self) -> PathBuf {
+        shellexpand::tilde(&self.data_path.to_string_lossy())
+            .into_owned()
+            .into()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct FileConfig {
     data_path: PathBuf,
     log_path: PathBuf,
+    #[serde(default)]
+    backup_count: usize,
+    #[serde(default = "default_pomodoro_minutes")]
+    pomodoro_minutes: u32,
+    #[serde(default = "default_break_minutes")]
+    break_minutes: u32,
+    #[serde(default)]
+    auto_check: bool,
+    #[serde(default)]
+    hooks: HashMap<EventKind, String>,
     keybinds: HashMap<String, HashMap<String, KeyAction>>,
 }
 
+fn default_pomodoro_minutes() -> u32 {
+    25
+}
+
+fn default_break_minutes() -> u32 {
+    5
+}
+
 pub fn get_default_app_data_path() -> PathBuf {
     let dirs = directories::ProjectDirs::from("com", "Tweoss", "Task List")
         .clone()
@@ -43,6 +82,11 @@ impl FileConfig {
                     Config {
                         data_path: self.data_path.clone(),
                         log_path: self.log_path.clone(),
+                        backup_count: self.backup_count,
+                        pomodoro_minutes: self.pomodoro_minutes,
+                        break_minutes: self.break_minutes,
+                        auto_check: self.auto_check,
+                        hooks: self.hooks.clone(),
                         keybinds: HashMap::new(),
                     },
                     e,
@@ -50,6 +94,11 @@ impl FileConfig {
             })?,
             data_path: self.data_path.clone(),
             log_path: self.log_path.clone(),
+            backup_count: self.backup_count,
+            pomodoro_minutes: self.pomodoro_minutes,
+            break_minutes: self.break_minutes,
+            auto_check: self.auto_check,
+            hooks: self.hooks.clone(),
         })
     }
 
@@ -57,6 +106,11 @@ impl FileConfig {
         let mut out = Self {
             data_path: get_default_app_data_path().join("tasks"),
             log_path: get_default_app_data_path().join("logs"),
+            backup_count: 0,
+            pomodoro_minutes: default_pomodoro_minutes(),
+            break_minutes: default_break_minutes(),
+            auto_check: false,
+            hooks: HashMap::new(),
             keybinds: HashMap::new(),
         };
         match out.read_from_file() {
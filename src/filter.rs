@@ -1,23 +1,43 @@
-use chrono::NaiveDateTime;
-use eyre::Result;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    ops::Range,
+    path::PathBuf,
+};
 
-use crate::storage::{BoxState, Data, Date, Task};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Weekday};
+use eyre::{Context, Result};
+use regex::Regex;
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash)]
-pub struct TaskID(usize);
+pub use crate::storage::TaskID;
+use crate::storage::{
+    BoxState, Conflict, Data, Date, Event, Node, Reload, Resolution, Task, Trashed,
+};
+pub use parser::parse_date;
 
 #[derive(Debug)]
 pub struct FilteredData {
     data: Data,
-    visible: Vec<usize>,
-    filter: Option<BooleanExpr>,
+    /// In the order of the query.
+    visible: Vec<TaskID>,
+    query: Query,
+    /// Of the visible tasks, if the query groups them.
+    groups: Vec<TaskGroup>,
+    /// When the visible tasks were last found, which relative dates in the
+    /// filter were resolved against.
+    filtered_on: NaiveDate,
+    cache: LeafCache,
 }
 impl FilteredData {
     pub fn new(data: Data) -> Self {
         Self {
-            visible: (0..data.tasks().len()).collect(),
+            visible: data.tasks().map(|(id, _)| id).collect(),
             data,
-            filter: None,
+            query: Query::default(),
+            groups: vec![],
+            filtered_on: Local::now().date_naive(),
+            cache: LeafCache::default(),
         }
     }
     pub fn iter(&self) -> Iter<'_> {
@@ -26,88 +46,311 @@ impl FilteredData {
             iter: self.visible.iter(),
         }
     }
+    pub fn ids(&self) -> impl Iterator<Item = TaskID> {
+        self.visible.iter().copied()
+    }
     pub fn len(&self) -> usize {
         self.visible.len()
     }
     pub fn is_empty(&self) -> bool {
         self.visible.is_empty()
     }
+    /// Of all tasks, shown or not.
+    pub fn total_len(&self) -> usize {
+        self.data.task_count()
+    }
+    pub fn groups(&self) -> &[TaskGroup] {
+        &self.groups
+    }
+    /// Of all tasks, shown or not, sorted.
+    pub fn tags(&self) -> BTreeSet<&str> {
+        self.data
+            .tasks()
+            .flat_map(|(_, t)| t.tags())
+            .map(String::as_str)
+            .collect()
+    }
 
     pub fn get_id(&self, visible_index: usize) -> TaskID {
-        TaskID(self.visible[visible_index])
+        self.visible[visible_index]
+    }
+    /// Where the task is shown, if it is.
+    pub fn visible_index(&self, task_id: TaskID) -> Option<usize> {
+        self.visible.iter().position(|id| *id == task_id)
     }
     pub fn get(&self, task_id: TaskID) -> Option<&Task> {
-        Some(&self.data.tasks()[task_id.0])
+        self.data.get(task_id)
     }
+    /// The task may be changed, so what it satisfies is forgotten.
     pub fn get_mut(&mut self, task_id: TaskID) -> Option<&mut Task> {
-        Some(&mut self.data.tasks_mut()[task_id.0])
+        self.cache.invalidate(task_id);
+        self.data.get_mut(task_id)
     }
-    pub fn set_completed(&mut self, index: usize, value: Option<Date>) {
-        self.data.set_completed(self.visible[index], value);
-        self.recalculate_is_visible(index);
+    pub fn set_completed(&mut self, task_id: TaskID, value: Option<Date>) {
+        self.data.set_completed(task_id, value);
+        self.recalculate_is_visible(task_id);
     }
-    pub fn push_box(&mut self, index: usize) {
-        self.data.push_box(self.visible[index]);
-        self.recalculate_is_visible(index);
+    pub fn push_box(&mut self, task_id: TaskID) {
+        self.data.push_box(task_id);
+        self.recalculate_is_visible(task_id);
     }
-    pub fn step_box_state(&mut self, index: usize, time: Date) -> Option<BoxState> {
-        let step_box_state = self.data.step_box_state(self.visible[index], time);
-        self.recalculate_is_visible(index);
+    pub fn step_box_state(&mut self, task_id: TaskID, time: Date) -> Option<BoxState> {
+        let step_box_state = self.data.step_box_state(task_id, time);
+        self.recalculate_is_visible(task_id);
         step_box_state
     }
-    pub fn remove_empty_state(&mut self, index: usize) {
-        self.data.remove_empty_state(self.visible[index]);
-        self.recalculate_is_visible(index);
+    pub fn void_box(&mut self, task_id: TaskID, time: Date, reason: Option<String>) -> bool {
+        let voided = self.data.void_box(task_id, time, reason);
+        self.recalculate_is_visible(task_id);
+        voided
+    }
+    pub fn check_started(&mut self, task_id: TaskID, end: Date) -> bool {
+        let checked = self.data.check_started(task_id, end);
+        self.recalculate_is_visible(task_id);
+        checked
+    }
+    pub fn remove_empty_state(&mut self, task_id: TaskID) {
+        self.data.remove_empty_state(task_id);
+        self.recalculate_is_visible(task_id);
+    }
+    pub fn set_title(&mut self, task_id: TaskID, title: String) {
+        self.data.set_title(task_id, title);
+        self.recalculate_is_visible(task_id);
     }
-    pub fn set_tags(&mut self, index: usize, tags: Vec<String>) {
-        self.data.set_tags(self.visible[index], tags);
-        self.recalculate_is_visible(index);
+    pub fn set_tags(&mut self, task_id: TaskID, tags: Vec<String>) {
+        self.data.set_tags(task_id, tags);
+        self.recalculate_is_visible(task_id);
     }
 
     pub fn write_dirty(&mut self) -> Result<()> {
         self.data.write_dirty()
     }
-    pub fn push(&mut self, task: Task) {
-        let new_index = self.data.tasks().len();
-        let visible_index = self.visible.len();
-        self.visible.push(new_index);
-        self.data.push(task);
-        self.recalculate_is_visible(visible_index);
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.data.take_events()
     }
-    fn recalculate_is_visible(&mut self, visible_index: usize) {
-        let Some(expr) = &self.filter else {
-            return;
+    pub fn task_path(&self, task_id: TaskID) -> Option<PathBuf> {
+        self.data.task_path(task_id)
+    }
+    pub fn push(&mut self, task: Task) -> TaskID {
+        let task_id = self.data.push(task);
+        self.visible.push(task_id);
+        self.recalculate_is_visible(task_id);
+        task_id
+    }
+    pub fn remove(&mut self, task_id: TaskID) -> Result<()> {
+        self.data.remove(task_id)?;
+        self.visible.retain(|id| *id != task_id);
+        self.order();
+        Ok(())
+    }
+    pub fn trash(&self) -> Result<Vec<Trashed>> {
+        self.data.trash()
+    }
+    pub fn restore(&mut self, trashed: &Trashed) -> Result<TaskID> {
+        let task_id = self.data.restore(trashed)?;
+        self.cache.clear();
+        self.refilter();
+        Ok(task_id)
+    }
+    fn recalculate_is_visible(&mut self, task_id: TaskID) {
+        self.cache.invalidate(task_id);
+        let now = Local::now().naive_local();
+        if let Some(expr) = &self.query.filter
+            && !self
+                .data
+                .get(task_id)
+                .is_some_and(|t| t.satisfies(expr, now))
+        {
+            self.visible.retain(|id| *id != task_id);
+        }
+        // The change may have moved the task.
+        self.order();
+    }
+
+    /// Keeps the current query if `input` doesn't parse.
+    pub fn set_filter(&mut self, input: &str) -> Result<(), FilterError> {
+        self.set_query(Query::from_str(input)?);
+        Ok(())
+    }
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+    pub fn set_query(&mut self, query: Query) {
+        self.query = query;
+        self.refilter();
+    }
+    fn refilter(&mut self) {
+        let now = Local::now().naive_local();
+        self.visible = match &self.query.filter {
+            Some(expr) => self.cache.filter(&self.data, expr, now),
+            None => self.data.tasks().map(|(id, _)| id).collect(),
         };
-        if !self.data.tasks()[self.visible[visible_index]].satisfies(expr) {
-            self.visible.remove(visible_index);
+        self.filtered_on = now.date();
+        self.order();
+    }
+    /// Sorts the visible tasks as the query asks, and groups them. Tasks
+    /// that compare equal stay in the order they were found in.
+    fn order(&mut self) {
+        let Query { sort, group, .. } = &self.query;
+        let group = *group;
+        if sort.is_empty() && group.is_none() {
+            self.groups.clear();
+            return;
+        }
+        let now = Local::now().naive_local();
+        let mut keyed: Vec<_> = self
+            .visible
+            .iter()
+            .map(|id| {
+                let task = self.data.get(*id).expect("visible tasks exist");
+                let group = group.and_then(|g| g.key(task));
+                let values: Vec<_> = sort.iter().map(|k| task.eval(&k.value, now)).collect();
+                (group, values, *id)
+            })
+            .collect();
+        keyed.sort_by(|(l_group, l_values, _), (r_group, r_values, _)| {
+            // Ungrouped tasks come last.
+            (l_group.is_none(), l_group)
+                .cmp(&(r_group.is_none(), r_group))
+                .then_with(|| {
+                    sort.iter()
+                        .zip(l_values.iter().zip(r_values))
+                        .map(|(key, (l, r))| {
+                            let ordering = l.sort_cmp(r);
+                            if key.descending {
+                                ordering.reverse()
+                            } else {
+                                ordering
+                            }
+                        })
+                        .find(|o| o.is_ne())
+                        .unwrap_or(Ordering::Equal)
+                })
+        });
+        self.groups.clear();
+        if let Some(grouping) = group {
+            for (i, (key, _, _)) in keyed.iter().enumerate() {
+                let name = key.as_deref().unwrap_or(grouping.missing());
+                match self.groups.last_mut() {
+                    Some(g) if g.name == name => g.len += 1,
+                    _ => self.groups.push(TaskGroup {
+                        name: name.to_string(),
+                        start: i,
+                        len: 1,
+                    }),
+                }
+            }
         }
+        self.visible = keyed.into_iter().map(|(_, _, id)| id).collect();
+    }
+    /// Refilters once `now` is a different day than the last filtering, so
+    /// that dates like `today` move on. Returns whether it refiltered.
+    pub fn refilter_if_new_day(&mut self, now: NaiveDateTime) -> bool {
+        if now.date() == self.filtered_on {
+            return false;
+        }
+        self.cache.clear();
+        self.refilter();
+        true
     }
 
-    pub fn set_filter(&mut self, input: &str) -> Result<()> {
-        let expr = BooleanExpr::from_str(input)?;
-        self.visible = self
-            .data
-            .tasks()
+    /// Applies changes made to task files by other programs. Returns the
+    /// changes that conflict with unsaved edits.
+    pub fn reload_paths(
+        &mut self,
+        paths: &[PathBuf],
+    ) -> Result<Vec<Conflict>, (Vec<Conflict>, eyre::Report)> {
+        let mut conflicts = vec![];
+        let mut error = Ok(());
+        for path in paths {
+            match self
+                .data
+                .reload_path(path)
+                .wrap_err_with(|| format!("reloading '{}'", path.display()))
+            {
+                Ok(Reload::Conflict(c)) => conflicts.push(c),
+                Ok(Reload::Unchanged | Reload::Added | Reload::Reloaded | Reload::Removed) => {}
+                Err(e) => error = error.and(Err(e)),
+            }
+        }
+        self.cache.clear();
+        self.refilter();
+        match error {
+            Ok(()) => Ok(conflicts),
+            Err(e) => Err((conflicts, e)),
+        }
+    }
+    pub fn resolve_conflict(&mut self, conflict: Conflict, resolution: Resolution) {
+        self.data.resolve_conflict(conflict, resolution);
+        self.cache.clear();
+        self.refilter();
+    }
+}
+
+/// Which tasks satisfy the leaves of filters, the conditions that aren't
+/// combinations of others, so that editing one part of a filter doesn't
+/// evaluate the other parts again.
+#[derive(Debug, Default)]
+struct LeafCache {
+    /// By the debug output of the leaf, which differs between leaves that
+    /// can evaluate differently.
+    results: HashMap<String, HashMap<TaskID, bool>>,
+}
+
+impl LeafCache {
+    fn invalidate(&mut self, task_id: TaskID) {
+        for results in self.results.values_mut() {
+            results.remove(&task_id);
+        }
+    }
+    fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    /// The tasks satisfying `expr`. Leaves that depend on the time of day
+    /// are evaluated again each time.
+    fn filter(&mut self, data: &Data, expr: &BooleanExpr, now: NaiveDateTime) -> Vec<TaskID> {
+        let mut leaves = vec![];
+        expr.leaves(&mut leaves);
+        let keys: Vec<_> = leaves.iter().map(|leaf| format!("{leaf:?}")).collect();
+        // Otherwise every filter typed would be kept.
+        self.results.retain(|key, _| keys.contains(key));
+        let mut volatile = HashMap::new();
+        for (leaf, key) in leaves.iter().zip(&keys) {
+            let results = if leaf.is_volatile() {
+                volatile.entry(key.clone()).or_default()
+            } else {
+                self.results.entry(key.clone()).or_default()
+            };
+            for (id, task) in data.tasks() {
+                results
+                    .entry(id)
+                    .or_insert_with(|| task.satisfies(leaf, now));
+            }
+        }
+        let columns: Vec<_> = keys
             .iter()
-            .enumerate()
-            .filter(|(_, t)| expr.as_ref().is_none_or(|expr| t.satisfies(expr)))
-            .map(|(i, _)| i)
+            .map(|key| volatile.get(key).unwrap_or_else(|| &self.results[key]))
             .collect();
-        self.filter = expr;
-        Ok(())
+        data.tasks()
+            .filter(|(id, _)| expr.combine(&mut 0, &|leaf| columns[leaf][id]))
+            .map(|(id, _)| id)
+            .collect()
     }
 }
 
 pub struct Iter<'a> {
     data: &'a Data,
-    iter: std::slice::Iter<'a, usize>,
+    iter: std::slice::Iter<'a, TaskID>,
 }
 
 impl<'a> Iterator for Iter<'a> {
     type Item = &'a Task;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|i| &self.data.tasks()[*i])
+        self.iter
+            .next()
+            .map(|id| self.data.get(*id).expect("visible tasks exist"))
     }
 }
 impl<'a> ExactSizeIterator for Iter<'a> {
@@ -119,24 +362,25 @@ impl<'a> ExactSizeIterator for Iter<'a> {
 impl Task {
     fn get_box(&self, index: isize) -> Option<BoxState> {
         if index >= 0 {
-            self.boxes().get(index as usize).copied()
+            self.boxes().get(index as usize).cloned()
         } else {
             // -1 should be last
             // -len should be 0th
             let offset = (-index) as usize;
             if offset <= self.boxes().len() {
-                self.boxes().get(self.boxes().len() - offset).copied()
+                self.boxes().get(self.boxes().len() - offset).cloned()
             } else {
                 None
             }
         }
     }
 
-    fn satisfies(&self, expr: &BooleanExpr) -> bool {
+    /// Relative dates in `expr` are resolved against `now`.
+    fn satisfies(&self, expr: &BooleanExpr, now: NaiveDateTime) -> bool {
         match expr {
-            BooleanExpr::Not(boolean_expr) => !self.satisfies(boolean_expr),
+            BooleanExpr::Not(boolean_expr) => !self.satisfies(boolean_expr, now),
             BooleanExpr::Compound { combinator, exprs } => {
-                let mut it = exprs.iter().map(|e| self.satisfies(e));
+                let mut it = exprs.iter().map(|e| self.satisfies(e, now));
                 match combinator {
                     Comb::And => it.all(|e| e),
                     Comb::Or => it.any(|e| e),
@@ -147,31 +391,136 @@ impl Task {
                 lhs,
                 rhs,
             } => {
-                let (lhs, rhs) = (self.eval(lhs), self.eval(rhs));
+                let (lhs, rhs) = (self.eval(lhs, now), self.eval(rhs, now));
                 match comparator {
+                    Comp::Lt => lhs < rhs,
                     Comp::Leq => lhs <= rhs,
+                    Comp::Gt => lhs > rhs,
                     Comp::Geq => lhs >= rhs,
                     Comp::Eq => lhs == rhs,
+                    Comp::Neq => lhs != rhs,
                 }
             }
-            BooleanExpr::Tag(t) => self.tags().contains(t),
+            BooleanExpr::Tag(pattern) => self.tags().iter().any(|t| pattern.is_match(t)),
             BooleanExpr::Box { index } => self.get_box(*index).is_some(),
             BooleanExpr::Completed => self.completed().is_some(),
+            BooleanExpr::Has(key) => self
+                .extra_fields()
+                .any(|(k, node)| k == key && node != Node::Null),
+            BooleanExpr::Worked { from, to } => {
+                let (from, to) = (from.resolve(now), to.resolve(now));
+                self.boxes()
+                    .iter()
+                    .filter_map(|b| b.worked(now))
+                    .any(|(start, end)| start <= to && from <= end)
+            }
+            BooleanExpr::Text { field, pattern } => match field {
+                TextField::Title => pattern.is_match(self.title()),
+                TextField::Body => pattern.is_match(&self.editable().inner().to_string()),
+            },
             BooleanExpr::Const(b) => *b,
         }
     }
-    fn eval(&self, expr: &ValueExpr) -> Value {
+    fn eval(&self, expr: &ValueExpr, now: NaiveDateTime) -> Value {
         match expr {
-            ValueExpr::Date(naive_date) => Value::Date(Some(*naive_date)),
+            ValueExpr::Date(date) => Value::Date(Some(date.resolve(now))),
             ValueExpr::Box { index } => Value::Box(self.get_box(*index)),
             ValueExpr::Completed => Value::Date(*self.completed()),
             ValueExpr::Created => Value::Date(Some(*self.created())),
-            ValueExpr::Started => Value::Box(Some(BoxState::Started)),
+            ValueExpr::Started => Value::Box(Some(BoxState::Started(None))),
             ValueExpr::Empty => Value::Box(Some(BoxState::Empty)),
+            // Only the state matters when compared.
+            ValueExpr::Voided => Value::Box(Some(BoxState::Voided {
+                start: None,
+                end: NaiveDateTime::MIN,
+                reason: None,
+            })),
+            ValueExpr::Int(n) => Value::Int(*n),
+            ValueExpr::Text(text) => Value::Text(Some(text.clone())),
+            ValueExpr::Field(key) => Value::Text(
+                self.extra_fields()
+                    .find(|(k, _)| k == key)
+                    .and_then(|(_, node)| node.as_scalar().map(str::to_string)),
+            ),
+            ValueExpr::Duration(delta) => Value::Duration(Some(*delta)),
+            ValueExpr::Count(Countable::Checked) => Value::Int(
+                self.boxes()
+                    .iter()
+                    .filter(|b| matches!(b, BoxState::Checked { .. }))
+                    .count() as i64,
+            ),
+            ValueExpr::Count(Countable::Boxes) => Value::Int(self.boxes().len() as i64),
+            ValueExpr::Count(Countable::Tags) => Value::Int(self.tags().len() as i64),
+            ValueExpr::Age => Value::Duration(Some(now - *self.created())),
+            ValueExpr::Elapsed { from, to } => {
+                let (from, to) = (self.eval(from, now).date(), self.eval(to, now).date());
+                Value::Duration(from.zip(to).map(|(from, to)| to - from))
+            }
         }
     }
 }
 
+/// A filter, and how to order the tasks that pass it.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    pub filter: Option<BooleanExpr>,
+    /// Compared in turn until one differs.
+    pub sort: Vec<SortKey>,
+    pub group: Option<Grouping>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SortKey {
+    pub value: ValueExpr,
+    pub descending: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Grouping {
+    /// By the first tag in alphabetical order, so each task is listed once.
+    Tag,
+}
+
+impl Grouping {
+    fn key(self, task: &Task) -> Option<String> {
+        match self {
+            Grouping::Tag => task.tags().iter().min().cloned(),
+        }
+    }
+    /// Name of the group of tasks without a key.
+    fn missing(self) -> &'static str {
+        match self {
+            Grouping::Tag => "(untagged)",
+        }
+    }
+}
+
+/// Consecutive visible tasks with the same group key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskGroup {
+    pub name: String,
+    /// Visible index of the first task.
+    pub start: usize,
+    pub len: usize,
+}
+
+/// Why a filter didn't parse.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterError {
+    /// Of the chars in question, empty at the end of the input.
+    pub span: Range<usize>,
+    /// What was expected, and what was found instead.
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at char {}", self.message, self.span.start)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
 #[derive(Clone, Debug)]
 pub enum BooleanExpr {
     Not(Box<BooleanExpr>),
@@ -184,27 +533,333 @@ pub enum BooleanExpr {
         lhs: ValueExpr,
         rhs: ValueExpr,
     },
-    Tag(String),
+    Tag(TagPattern),
     Box {
         index: isize,
     },
     Completed,
+    /// The frontmatter field is set.
+    Has(String),
+    /// Some box was worked on during the period.
+    Worked {
+        from: DateExpr,
+        to: DateExpr,
+    },
+    /// The title or body matches.
+    Text {
+        field: TextField,
+        pattern: TextPattern,
+    },
     Const(bool),
 }
 
+/// Tags nest like paths, as in `work/client/bug`.
+#[derive(Clone, Debug)]
+pub enum TagPattern {
+    /// The tag, or one nested under it.
+    Name(String),
+    /// `*` matches a level, or part of one, and `**` any number of levels,
+    /// none included.
+    Glob(String),
+    Regex(Regex),
+}
+
+impl TagPattern {
+    fn is_match(&self, tag: &str) -> bool {
+        match self {
+            TagPattern::Name(name) => tag
+                .strip_prefix(name.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/')),
+            TagPattern::Glob(glob) => {
+                let glob: Vec<&str> = glob.split('/').collect();
+                let tag: Vec<&str> = tag.split('/').collect();
+                glob_match(&glob, &tag)
+            }
+            TagPattern::Regex(regex) => regex.is_match(tag),
+        }
+    }
+}
+
+fn glob_match(glob: &[&str], tag: &[&str]) -> bool {
+    match glob.split_first() {
+        None => tag.is_empty(),
+        Some((&"**", glob)) => (0..=tag.len()).any(|i| glob_match(glob, &tag[i..])),
+        Some((level, glob)) => tag
+            .split_first()
+            .is_some_and(|(first, tag)| level_match(level, first) && glob_match(glob, tag)),
+    }
+}
+
+/// `*` matches any part of the level.
+fn level_match(glob: &str, level: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = level.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[derive(Clone, Debug)]
+pub enum TextField {
+    Title,
+    Body,
+}
+
+/// Matches text regardless of case, unless a regex turns that off with
+/// `(?-i)`.
+#[derive(Clone, Debug)]
+pub enum TextPattern {
+    /// Lowercase.
+    Contains(String),
+    Regex(Regex),
+}
+
+impl TextPattern {
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            TextPattern::Contains(needle) => text.to_lowercase().contains(needle),
+            TextPattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl BooleanExpr {
+    /// Appends the conditions that aren't combinations of others, depth
+    /// first.
+    fn leaves<'a>(&'a self, leaves: &mut Vec<&'a BooleanExpr>) {
+        match self {
+            BooleanExpr::Not(expr) => expr.leaves(leaves),
+            BooleanExpr::Compound { exprs, .. } => {
+                for expr in exprs {
+                    expr.leaves(leaves);
+                }
+            }
+            leaf => leaves.push(leaf),
+        }
+    }
+    /// Combines the results of the leaves, numbered as by `leaves` from
+    /// `next`.
+    fn combine(&self, next: &mut usize, leaf: &impl Fn(usize) -> bool) -> bool {
+        match self {
+            BooleanExpr::Not(expr) => !expr.combine(next, leaf),
+            BooleanExpr::Compound { combinator, exprs } => {
+                exprs
+                    .iter()
+                    .fold(matches!(combinator, Comb::And), |result, expr| {
+                        // Every leaf is numbered, even once the result is known.
+                        let satisfied = expr.combine(next, leaf);
+                        match combinator {
+                            Comb::And => result && satisfied,
+                            Comb::Or => result || satisfied,
+                        }
+                    })
+            }
+            _ => {
+                *next += 1;
+                leaf(*next - 1)
+            }
+        }
+    }
+    /// Whether the leaf can change as time passes within a day.
+    fn is_volatile(&self) -> bool {
+        match self {
+            // Started boxes are worked on until now.
+            BooleanExpr::Worked { .. } => true,
+            BooleanExpr::Comparison { lhs, rhs, .. } => lhs.is_volatile() || rhs.is_volatile(),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ValueExpr {
-    Date(NaiveDateTime),
-    Box { index: isize },
+    Date(DateExpr),
+    Box {
+        index: isize,
+    },
     Completed,
     Created,
     Started,
     Empty,
+    Voided,
+    Int(i64),
+    Text(String),
+    /// A frontmatter field without special meaning.
+    Field(String),
+    Duration(TimeDelta),
+    Count(Countable),
+    /// Since the task was created.
+    Age,
+    /// From one date to another, unset if either is.
+    Elapsed {
+        from: Box<ValueExpr>,
+        to: Box<ValueExpr>,
+    },
+}
+
+impl ValueExpr {
+    fn is_volatile(&self) -> bool {
+        match self {
+            ValueExpr::Date(date) => date.is_volatile(),
+            ValueExpr::Age => true,
+            ValueExpr::Elapsed { from, to } => from.is_volatile() || to.is_volatile(),
+            _ => false,
+        }
+    }
+    fn kind(&self) -> Kind {
+        match self {
+            ValueExpr::Date(_) | ValueExpr::Completed | ValueExpr::Created => Kind::Date,
+            ValueExpr::Box { .. } | ValueExpr::Started | ValueExpr::Empty | ValueExpr::Voided => {
+                Kind::Box
+            }
+            ValueExpr::Int(_) | ValueExpr::Count(_) => Kind::Number,
+            ValueExpr::Text(_) | ValueExpr::Field(_) => Kind::Text,
+            ValueExpr::Duration(_) | ValueExpr::Age | ValueExpr::Elapsed { .. } => Kind::Duration,
+        }
+    }
+}
+
+/// What a value evaluates to, so comparisons that never hold are rejected
+/// when parsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Kind {
+    Date,
+    Box,
+    Number,
+    Text,
+    Duration,
+}
+
+impl Kind {
+    /// Boxes compare as dates. Text reads as a number or a date when compared
+    /// with one, but never as a duration.
+    fn compares_with(self, other: Kind) -> bool {
+        match (self, other) {
+            (Kind::Text, Kind::Duration) | (Kind::Duration, Kind::Text) => false,
+            (Kind::Text, _) | (_, Kind::Text) => true,
+            (Kind::Date | Kind::Box, Kind::Date | Kind::Box) => true,
+            (l, r) => l == r,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Date => "a date",
+            Kind::Box => "a box",
+            Kind::Number => "a number",
+            Kind::Text => "text",
+            Kind::Duration => "a duration",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Countable {
+    Checked,
+    Boxes,
+    Tags,
+}
+
+/// A date in a filter. Relative dates are resolved each time the filter is
+/// evaluated, so saved filters stay current.
+#[derive(Clone, Debug)]
+pub enum DateExpr {
+    Absolute(NaiveDateTime),
+    Now,
+    /// Midnight, this many days after today.
+    Day(i64),
+    /// Midnight on Monday.
+    StartOfWeek,
+    StartOfMonth,
+    /// Midnight on the last such weekday before today.
+    Last(Weekday),
+    /// Added to now.
+    Offset(TimeDelta),
+}
+
+impl DateExpr {
+    /// Whether it resolves to a different time later the same day.
+    fn is_volatile(&self) -> bool {
+        matches!(self, DateExpr::Now | DateExpr::Offset(_))
+    }
+    fn resolve(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let today = now.date();
+        let days_before =
+            |days: u32| (today - TimeDelta::days(days.into())).and_time(NaiveTime::MIN);
+        match self {
+            DateExpr::Absolute(date) => *date,
+            DateExpr::Now => now,
+            DateExpr::Day(offset) => (today + TimeDelta::days(*offset)).and_time(NaiveTime::MIN),
+            DateExpr::StartOfWeek => days_before(today.weekday().num_days_from_monday()),
+            DateExpr::StartOfMonth => days_before(today.day0()),
+            DateExpr::Last(weekday) => {
+                let since = today.weekday().days_since(*weekday);
+                days_before(if since == 0 { 7 } else { since })
+            }
+            // Past the range of dates, the earliest or latest one.
+            DateExpr::Offset(delta) => {
+                now.checked_add_signed(*delta)
+                    .unwrap_or(if *delta < TimeDelta::zero() {
+                        NaiveDateTime::MIN
+                    } else {
+                        NaiveDateTime::MAX
+                    })
+            }
+        }
+    }
 }
 
 enum Value {
     Date(Option<NaiveDateTime>),
     Box(Option<BoxState>),
+    Int(i64),
+    /// Compared as a number or a date with those, if it reads as one. Unset
+    /// text can't be compared.
+    Text(Option<String>),
+    /// Unset durations can't be compared.
+    Duration(Option<TimeDelta>),
+}
+
+impl Value {
+    /// A total order for sorting, unlike comparisons in filters. Unset
+    /// values come first.
+    fn sort_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l.cmp(r),
+            (Value::Duration(l), Value::Duration(r)) => l.cmp(r),
+            (Value::Text(l), Value::Text(r)) => match (l, r) {
+                (Some(l), Some(r)) => text_cmp(l, r),
+                _ => l.cmp(r),
+            },
+            (l, r) => l.date().cmp(&r.date()),
+        }
+    }
+    /// The date of a date, or when a box ended or was started.
+    fn date(&self) -> Option<NaiveDateTime> {
+        match self {
+            Value::Date(date) => *date,
+            Value::Text(text) => crate::storage::parse_date(text.as_deref()?),
+            Value::Box(Some(BoxState::Checked { end, .. } | BoxState::Voided { end, .. })) => {
+                Some(*end)
+            }
+            Value::Box(Some(BoxState::Started(start))) => *start,
+            _ => None,
+        }
+    }
 }
 
 impl PartialEq for Value {
@@ -218,21 +873,51 @@ impl PartialOrd for Value {
         match (self, other) {
             (Self::Date(l), Self::Date(r)) => l.partial_cmp(r),
             (Self::Box(l), Self::Box(r)) => match (l.as_ref()?, r.as_ref()?) {
-                (BoxState::Checked(l), BoxState::Checked(r)) => l.partial_cmp(r),
-                (BoxState::Started, BoxState::Started) => Some(std::cmp::Ordering::Equal),
+                (BoxState::Checked { end: l, .. }, BoxState::Checked { end: r, .. }) => {
+                    l.partial_cmp(r)
+                }
+                (BoxState::Started(_), BoxState::Started(_)) => Some(std::cmp::Ordering::Equal),
                 (BoxState::Empty, BoxState::Empty) => Some(std::cmp::Ordering::Equal),
+                (BoxState::Voided { .. }, BoxState::Voided { .. }) => {
+                    Some(std::cmp::Ordering::Equal)
+                }
                 _ => None,
             },
-            (Self::Date(l), Self::Box(r)) | (Self::Box(r), Self::Date(l)) => {
-                match (l.as_ref()?, r.as_ref()?) {
-                    (date, BoxState::Checked(box_time)) => date.partial_cmp(box_time),
-                    _ => None,
+            (Self::Date(l), Self::Box(r)) => match (l.as_ref()?, r.as_ref()?) {
+                (date, BoxState::Checked { end, .. } | BoxState::Voided { end, .. }) => {
+                    date.partial_cmp(end)
                 }
+                (date, BoxState::Started(Some(start))) => date.partial_cmp(start),
+                _ => None,
+            },
+            (Self::Box(_), Self::Date(_)) => other.partial_cmp(self).map(|o| o.reverse()),
+            (Self::Int(l), Self::Int(r)) => l.partial_cmp(r),
+            (Self::Duration(l), Self::Duration(r)) => l.as_ref()?.partial_cmp(r.as_ref()?),
+            (Self::Text(l), Self::Text(r)) => Some(text_cmp(l.as_ref()?, r.as_ref()?)),
+            (Self::Text(text), Self::Int(n)) => {
+                let text: f64 = text.as_ref()?.trim().parse().ok()?;
+                text.partial_cmp(&(*n as f64))
+            }
+            (Self::Int(_), Self::Text(_)) => other.partial_cmp(self).map(|o| o.reverse()),
+            (Self::Text(_), Self::Date(_) | Self::Box(_)) => {
+                Self::Date(Some(self.date()?)).partial_cmp(other)
             }
+            (Self::Date(_) | Self::Box(_), Self::Text(_)) => {
+                other.partial_cmp(self).map(|o| o.reverse())
+            }
+            _ => None,
         }
     }
 }
 
+/// As numbers if both read as one.
+fn text_cmp(l: &str, r: &str) -> Ordering {
+    match (l.trim().parse::<f64>(), r.trim().parse::<f64>()) {
+        (Ok(l), Ok(r)) => l.total_cmp(&r),
+        _ => l.cmp(r),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Comb {
     And,
@@ -241,75 +926,269 @@ pub enum Comb {
 
 #[derive(Clone, Debug)]
 pub enum Comp {
+    Lt,
     Leq,
+    Gt,
     Geq,
     Eq,
+    Neq,
 }
 
 mod parser {
     // filter expression grammar:
-    // filter = '(' delimited(filter, '|') ')' | '(' delimited(filter, '&') ')' | 'not' filter | existence | comparison
-    // existence = 'completed' | 'box'[i]
-    // comparison = value operator reference
-    // value = 'created' | 'completed' | 'box'[i] | 'started' | 'empty'
-    // operator = '>=' | '<=' | '='
-    // reference = date
-    // date = '"' \d{4}-\d{2}-\d{2} \d{2}:\d{2} '"'
-    //
-    // maybe in future also, 'name' 'contains' string
-    //
-
-    use chrono::NaiveDate;
+    // query = [filter] (sort | group)*
+    // sort = 'sort by' delimited(value ['asc' | 'desc'], ',')
+    // group = 'group by' 'tag'
+    // filter = delimited(conjunction, '|' | 'or')
+    // conjunction = delimited(negation, '&' | 'and')
+    // negation = 'not' negation | atom
+    // atom = '(' filter ')' | '()' | existence | comparison | search | 'true' | 'false'
+    // existence = 'completed' | 'box'[i] | 'worked(' date ',' date ')' | 'tag(' tag ')'
+    //           | 'has(' key ')'
+    // tag = '~' regex | [^()]+
+    // comparison = value operator value, of types that compare
+    // value = date_value | 'started' | 'empty' | 'voided' | 'age' | \d+ | duration | string
+    //       | 'count(' ('checked' | 'boxes' | 'tags') ')' | 'duration(' date_value ',' date_value ')'
+    //       | 'field(' key ')'
+    // key = [^()]+
+    // date_value = 'created' | 'completed' | 'box'[i] | date
+    // operator = '<=' | '<' | '>=' | '>' | '!=' | '='
+    // duration = \d+ ('m' | 'h' | 'd' | 'w')
+    // date = \d{4}-\d{2}-\d{2} [('T' | \s*) \d{2}:\d{2}] | 'now' | 'today' | 'yesterday' | 'tomorrow'
+    //      | 'start_of_week' | 'start_of_month' | 'this week' | 'this month'
+    //      | 'last' weekday | ('+' | '-') duration
+    // search = ('title' | 'body') ('contains' string | '~' regex)
+    // string = '"' ([^"\\] | '\\' any)* '"'
+    // regex = '/' ([^/\\] | '\\' any)* '/'
+
+    use chrono::{Local, NaiveDate, NaiveDateTime, TimeDelta, Weekday};
+    use chumsky::error::{RichPattern, RichReason};
     use chumsky::{
         Parser,
         error::Rich,
         extra,
         prelude::*,
-        text::{digits, whitespace},
+        text::{ascii::keyword, digits, whitespace},
     };
-    use eyre::{Result, eyre};
+    use eyre::Result;
 
-    use crate::filter::{BooleanExpr, Comp, ValueExpr};
+    use regex::RegexBuilder;
 
-    impl super::BooleanExpr {
-        pub fn from_str(input: &str) -> Result<Option<super::BooleanExpr>> {
-            expr()
-                .map(Some)
-                .or(empty().to(None))
+    use crate::filter::{
+        BooleanExpr, Comb, Comp, Countable, DateExpr, FilterError, Grouping, Query, SortKey,
+        TagPattern, TextField, TextPattern, ValueExpr,
+    };
+
+    impl Query {
+        pub fn from_str(input: &str) -> std::result::Result<Query, FilterError> {
+            query()
                 .parse(input)
                 .into_result()
-                .map_err(|e| {
-                    let Some(e) = e.first() else {
-                        return eyre!("missing error");
+                .map_err(|errors| FilterError::new(input, errors))
+        }
+    }
+
+    impl FilterError {
+        /// Describes the first of `errors`, the one furthest into the input.
+        fn new(input: &str, errors: Vec<Rich<char>>) -> Self {
+            let Some(e) = errors.first() else {
+                return Self {
+                    span: 0..0,
+                    message: "missing error".to_string(),
+                };
+            };
+            let chars = |byte: usize| input[..byte].chars().count();
+            let span = chars(e.span().start)..chars(e.span().end);
+            let message = match e.reason() {
+                RichReason::ExpectedFound { expected, found } => {
+                    let mut expected: Vec<_> = expected
+                        .iter()
+                        .filter_map(|p| match p {
+                            // Says nothing when listed with other tokens.
+                            RichPattern::Any => None,
+                            // Keywords are kept debug formatted.
+                            RichPattern::Identifier(i) => {
+                                Some(format!("'{}'", i.trim_matches('"')))
+                            }
+                            p => Some(p.to_string()),
+                        })
+                        .collect();
+                    expected.sort();
+                    expected.dedup();
+                    let found = match found {
+                        Some(c) => format!("'{}'", **c),
+                        None => "end of input".to_string(),
                     };
-                    eyre!(
-                        "parsing fields encountered {} at char {}",
-                        e.reason(),
-                        e.span().start
-                    )
-                })
+                    match &expected[..] {
+                        [] => format!("unexpected {found}"),
+                        [only] => format!("expected {only}, found {found}"),
+                        [first, last] => format!("expected {first} or {last}, found {found}"),
+                        [rest @ .., last] => {
+                            format!("expected {}, or {last}, found {found}", rest.join(", "))
+                        }
+                    }
+                }
+                RichReason::Custom(message) => message.clone(),
+            };
+            Self { span, message }
         }
     }
 
-    fn expr<'src>() -> impl Parser<'src, &'src str, BooleanExpr, extra::Err<Rich<'src, char>>> {
-        fn parse_int<'src>(
-            n: &'src str,
-            span: SimpleSpan,
-        ) -> std::result::Result<isize, Rich<'src, char>> {
-            n.parse::<isize>().map_err(|e| Rich::custom(span, e))
-        }
-        fn parse_uint<'src>(
-            n: &'src str,
-            span: SimpleSpan,
-        ) -> std::result::Result<usize, Rich<'src, char>> {
-            n.parse::<usize>().map_err(|e| Rich::custom(span, e))
-        }
-        fn digit_count<'src>(
-            count: usize,
-        ) -> impl Parser<'src, &'src str, usize, extra::Err<Rich<'src, char>>> + Clone {
-            digits(10).exactly(count).to_slice().try_map(parse_uint)
-        }
-        let date_expr = choice((
+    /// Parses a date on its own, as written in filters, resolving relative
+    /// dates against now.
+    pub fn parse_date(input: &str) -> Result<NaiveDateTime> {
+        let date = date()
+            .padded()
+            .parse(input)
+            .into_result()
+            .map_err(|errors| FilterError::new(input, errors))?;
+        Ok(date.resolve(Local::now().naive_local()))
+    }
+
+    fn parse_uint<'src>(
+        n: &'src str,
+        span: SimpleSpan,
+    ) -> std::result::Result<usize, Rich<'src, char>> {
+        n.parse::<usize>().map_err(|e| Rich::custom(span, e))
+    }
+
+    fn digit_count<'src>(
+        count: usize,
+    ) -> impl Parser<'src, &'src str, usize, extra::Err<Rich<'src, char>>> + Clone {
+        digits(10).exactly(count).to_slice().try_map(parse_uint)
+    }
+
+    fn date<'src>() -> impl Parser<'src, &'src str, DateExpr, extra::Err<Rich<'src, char>>> + Clone
+    {
+        let weekday = choice((
+            just("monday").to(Weekday::Mon),
+            just("tuesday").to(Weekday::Tue),
+            just("wednesday").to(Weekday::Wed),
+            just("thursday").to(Weekday::Thu),
+            just("friday").to(Weekday::Fri),
+            just("saturday").to(Weekday::Sat),
+            just("sunday").to(Weekday::Sun),
+        ))
+        .labelled("weekday");
+        let offset = one_of("+-")
+            .then(duration())
+            .map(|(sign, delta)| if sign == '-' { -delta } else { delta });
+        choice((
+            absolute_date().map(DateExpr::Absolute),
+            just("now").to(DateExpr::Now),
+            just("today").to(DateExpr::Day(0)),
+            just("yesterday").to(DateExpr::Day(-1)),
+            just("tomorrow").to(DateExpr::Day(1)),
+            just("start_of_week").to(DateExpr::StartOfWeek),
+            just("start_of_month").to(DateExpr::StartOfMonth),
+            just("this")
+                .then(whitespace().at_least(1))
+                .ignore_then(choice((
+                    just("week").to(DateExpr::StartOfWeek),
+                    just("month").to(DateExpr::StartOfMonth),
+                ))),
+            just("last")
+                .then(whitespace().at_least(1))
+                .ignore_then(weekday)
+                .map(DateExpr::Last),
+            offset.map(DateExpr::Offset),
+        ))
+        .labelled("date")
+    }
+
+    /// Longer durations are an error, so offsets from now stay dates.
+    pub(super) const MAX_DURATION: TimeDelta = TimeDelta::weeks(52 * 10_000);
+
+    /// A number of minutes, hours, days, or weeks, as in `3d`.
+    fn duration<'src>()
+    -> impl Parser<'src, &'src str, TimeDelta, extra::Err<Rich<'src, char>>> + Clone {
+        let unit = choice((
+            just('m').to(TimeDelta::minutes(1)),
+            just('h').to(TimeDelta::hours(1)),
+            just('d').to(TimeDelta::days(1)),
+            just('w').to(TimeDelta::weeks(1)),
+        ));
+        digits(10)
+            .to_slice()
+            .try_map(parse_uint)
+            .then(unit)
+            // Reported rather than backtracked from, or the error would be
+            // about what else the digits could have started.
+            .validate(|(count, unit), extra, emitter| {
+                i32::try_from(count)
+                    .ok()
+                    .and_then(|count| unit.checked_mul(count))
+                    .filter(|duration| *duration <= MAX_DURATION)
+                    .unwrap_or_else(|| {
+                        emitter.emit(Rich::custom(extra.span(), "duration too long"));
+                        TimeDelta::zero()
+                    })
+            })
+            .labelled("duration")
+    }
+
+    /// A day, at midnight unless a time is given after a `T` or any
+    /// whitespace, none included.
+    fn absolute_date<'src>()
+    -> impl Parser<'src, &'src str, NaiveDateTime, extra::Err<Rich<'src, char>>> + Clone {
+        let time = digit_count(2).then_ignore(just(":")).then(digit_count(2));
+        digit_count(4)
+            .then_ignore(just("-"))
+            .then(digit_count(2))
+            .then_ignore(just("-"))
+            .then(digit_count(2))
+            .then(
+                just('T')
+                    .ignored()
+                    .or(whitespace())
+                    .ignore_then(time)
+                    .or_not(),
+            )
+            // Reported rather than backtracked from, or `2024` would be
+            // tried as a number and the error be about the `-` after it.
+            .validate(|(((y, m), d), time), extra, emitter| {
+                let (hour, min) = time.unwrap_or((0, 0));
+                NaiveDate::from_ymd_opt(y as i32, m as u32, d as u32)
+                    .and_then(|d| d.and_hms_opt(hour as u32, min as u32, 0))
+                    .unwrap_or_else(|| {
+                        emitter.emit(Rich::custom(extra.span(), "invalid date"));
+                        NaiveDateTime::MIN
+                    })
+            })
+    }
+
+    fn parse_int<'src>(
+        n: &'src str,
+        span: SimpleSpan,
+    ) -> std::result::Result<isize, Rich<'src, char>> {
+        n.parse::<isize>().map_err(|e| Rich::custom(span, e))
+    }
+
+    fn string<'src>() -> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>> + Clone
+    {
+        none_of("\\\"")
+            .or(just('\\').ignore_then(any()))
+            .repeated()
+            .collect::<String>()
+            .delimited_by(just('"'), just('"'))
+            .labelled("string")
+    }
+
+    /// The name of a frontmatter field, between parentheses.
+    fn key<'src>() -> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>> + Clone {
+        none_of("()")
+            .repeated()
+            .at_least(1)
+            .to_slice()
+            .map(|key: &str| key.trim().to_string())
+            .labelled("field name")
+            .delimited_by(just("("), just(")"))
+    }
+
+    /// Something a task has, or a literal to compare it with.
+    fn value<'src>() -> impl Parser<'src, &'src str, ValueExpr, extra::Err<Rich<'src, char>>> + Clone
+    {
+        let date_value = choice((
             just("completed").to(ValueExpr::Completed),
             just("created").to(ValueExpr::Created),
             just("box[").ignore_then(
@@ -322,68 +1201,212 @@ mod parser {
                     .then_ignore(just("]"))
                     .map(|index| ValueExpr::Box { index }),
             ),
-            digit_count(4)
-                .then_ignore(just("-"))
-                .then(digit_count(2))
-                .then_ignore(just("-"))
-                .then(digit_count(2))
-                .then_ignore(whitespace())
-                .then(digit_count(2))
-                .then_ignore(just(":"))
-                .then(digit_count(2))
-                .try_map(|((((y, m), d), hour), min), span| {
-                    NaiveDate::from_ymd_opt(y as i32, m as u32, d as u32)
-                        .and_then(|d| d.and_hms_opt(hour as u32, min as u32, 0))
-                        .ok_or_else(|| Rich::custom(span, "invalid date"))
-                })
-                .map(ValueExpr::Date),
+            date().map(ValueExpr::Date),
+        ))
+        .padded();
+        choice((
+            date_value.clone(),
             just("started").to(ValueExpr::Started),
             just("empty").to(ValueExpr::Empty),
+            just("voided").to(ValueExpr::Voided),
+            just("age").to(ValueExpr::Age),
+            just("count(")
+                .ignore_then(
+                    choice((
+                        just("checked").to(Countable::Checked),
+                        just("boxes").to(Countable::Boxes),
+                        just("tags").to(Countable::Tags),
+                    ))
+                    .labelled("checked, boxes, or tags")
+                    .padded(),
+                )
+                .then_ignore(just(")"))
+                .map(ValueExpr::Count),
+            just("duration(")
+                .ignore_then(date_value.clone())
+                .then_ignore(just(","))
+                .then(date_value)
+                .then_ignore(just(")"))
+                .map(|(from, to)| ValueExpr::Elapsed {
+                    from: Box::new(from),
+                    to: Box::new(to),
+                }),
+            just("field").ignore_then(key()).map(ValueExpr::Field),
+            string().map(ValueExpr::Text),
+            duration().map(ValueExpr::Duration),
+            digits(10)
+                .to_slice()
+                .try_map(|n: &str, span| n.parse().map_err(|e| Rich::custom(span, e)))
+                .map(ValueExpr::Int),
         ))
-        .padded();
+        .labelled("value")
+        .padded()
+    }
 
-        recursive(|expr| {
-            choice((
-                just("not ")
-                    .ignore_then(expr.clone())
-                    .map(|e| BooleanExpr::Not(Box::new(e))),
-                expr.clone()
-                    .separated_by(just('|'))
-                    .collect::<Vec<_>>()
-                    .delimited_by(just('('), just(')'))
-                    .map(|exprs| BooleanExpr::Compound {
-                        combinator: super::Comb::Or,
-                        exprs,
-                    }),
-                expr.clone()
-                    .separated_by(just('&'))
-                    .collect::<Vec<_>>()
-                    .delimited_by(just('('), just(')'))
-                    .map(|exprs| BooleanExpr::Compound {
-                        combinator: super::Comb::And,
+    fn query<'src>() -> impl Parser<'src, &'src str, Query, extra::Err<Rich<'src, char>>> {
+        #[derive(Clone)]
+        enum Clause {
+            Sort(Vec<SortKey>),
+            Group(Grouping),
+        }
+        let by = |clause| {
+            keyword(clause)
+                .then(whitespace().at_least(1))
+                .then(keyword("by"))
+        };
+        let sort_key = value()
+            .then(
+                choice((keyword("asc").to(false), keyword("desc").to(true)))
+                    .padded()
+                    .or_not(),
+            )
+            .map(|(value, descending)| SortKey {
+                value,
+                descending: descending.unwrap_or(false),
+            });
+        let sort = by("sort").ignore_then(
+            sort_key
+                .separated_by(just(','))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .map(Clause::Sort),
+        );
+        let group = by("group").ignore_then(
+            keyword("tag")
+                .padded()
+                .to(Clause::Group(Grouping::Tag))
+                .labelled("grouping"),
+        );
+        expr()
+            .or_not()
+            .then(
+                choice((sort, group))
+                    .padded()
+                    .repeated()
+                    .collect::<Vec<_>>(),
+            )
+            .map(|(filter, clauses)| {
+                let mut query = Query {
+                    filter,
+                    ..Query::default()
+                };
+                // Later clauses replace earlier ones.
+                for clause in clauses {
+                    match clause {
+                        Clause::Sort(keys) => query.sort = keys,
+                        Clause::Group(grouping) => query.group = Some(grouping),
+                    }
+                }
+                query
+            })
+            .padded()
+    }
+
+    fn expr<'src>() -> impl Parser<'src, &'src str, BooleanExpr, extra::Err<Rich<'src, char>>> {
+        let date = date();
+        let value = value();
+
+        // Escapes are left to the regex, except for the delimiter.
+        let regex = none_of("\\/")
+            .ignored()
+            .or(just('\\').then(any()).ignored())
+            .repeated()
+            .to_slice()
+            .delimited_by(just('/'), just('/'))
+            .labelled("regex")
+            .try_map(|re: &str, span| {
+                RegexBuilder::new(&re.replace("\\/", "/"))
+                    .case_insensitive(true)
+                    .build()
+                    // The last line says what is wrong, the others point at it.
+                    .map_err(|e| {
+                        let e = e.to_string();
+                        let e = e.lines().last().unwrap_or_default().trim();
+                        Rich::custom(span, e.trim_start_matches("error: "))
+                    })
+            });
+        let search = choice((
+            just("title").to(TextField::Title),
+            just("body").to(TextField::Body),
+        ))
+        .then(choice((
+            just("contains")
+                .padded()
+                .ignore_then(string())
+                .map(|s| TextPattern::Contains(s.to_lowercase())),
+            just("~")
+                .padded()
+                .ignore_then(regex)
+                .map(TextPattern::Regex),
+        )))
+        .map(|(field, pattern)| BooleanExpr::Text { field, pattern });
+
+        // Terms bound by the same operator are collected into one compound.
+        let compound = |combinator: Comb| {
+            move |mut exprs: Vec<BooleanExpr>| {
+                if exprs.len() == 1 {
+                    exprs.pop().unwrap()
+                } else {
+                    BooleanExpr::Compound {
+                        combinator: combinator.clone(),
                         exprs,
-                    }),
-                date_expr
+                    }
+                }
+            }
+        };
+
+        recursive(|expr| {
+            let atom = choice((
+                expr.delimited_by(just('('), just(')')),
+                // Nothing to satisfy.
+                just("()").to(BooleanExpr::Compound {
+                    combinator: Comb::Or,
+                    exprs: vec![],
+                }),
+                value
                     .clone()
-                    .then(choice((
-                        just("<=").to(Comp::Leq),
-                        just(">=").to(Comp::Geq),
-                        just("=").to(Comp::Eq),
-                    )))
-                    .then(date_expr.clone())
-                    .map(|((lhs, comparator), rhs)| BooleanExpr::Comparison {
-                        lhs,
-                        rhs,
-                        comparator,
+                    .then(
+                        choice((
+                            just("<=").to(Comp::Leq),
+                            just("<").to(Comp::Lt),
+                            just(">=").to(Comp::Geq),
+                            just(">").to(Comp::Gt),
+                            just("!=").to(Comp::Neq),
+                            just("=").to(Comp::Eq),
+                        ))
+                        .labelled("comparison"),
+                    )
+                    .then(value.clone())
+                    .validate(|((lhs, comparator), rhs), extra, emitter| {
+                        let (l, r) = (lhs.kind(), rhs.kind());
+                        if !l.compares_with(r) {
+                            emitter.emit(Rich::custom(
+                                extra.span(),
+                                format!("can't compare {l} with {r}"),
+                            ));
+                        }
+                        BooleanExpr::Comparison {
+                            lhs,
+                            rhs,
+                            comparator,
+                        }
                     }),
                 just("tag(")
-                    .ignore_then(
-                        any()
-                            .filter(|c: &char| *c != '(' && *c != ')')
+                    .ignore_then(choice((
+                        // Tags can't contain '~', so this is no tag's name.
+                        just("~").padded().ignore_then(regex).map(TagPattern::Regex),
+                        none_of("()")
                             .repeated()
                             .at_least(1)
-                            .collect::<String>(),
-                    )
+                            .to_slice()
+                            .map(|tag: &str| {
+                                if tag.contains('*') {
+                                    TagPattern::Glob(tag.to_string())
+                                } else {
+                                    TagPattern::Name(tag.to_string())
+                                }
+                            }),
+                    )))
                     .then_ignore(just(")"))
                     .map(BooleanExpr::Tag),
                 just("box[").ignore_then(
@@ -397,12 +1420,276 @@ mod parser {
                         .map(|index| BooleanExpr::Box { index }),
                 ),
                 just("completed").to(BooleanExpr::Completed),
+                just("has").ignore_then(key()).map(BooleanExpr::Has),
+                just("worked(")
+                    .ignore_then(date.clone().padded())
+                    .then_ignore(just(","))
+                    .then(date.clone().padded())
+                    .then_ignore(just(")"))
+                    .map(|(from, to)| BooleanExpr::Worked { from, to }),
+                search.clone(),
                 just("true")
                     .to(true)
                     .or(just("false").to(false))
                     .map(BooleanExpr::Const),
             ))
-            .padded()
+            .labelled("condition")
+            .padded();
+            let negation = recursive(|negation| {
+                keyword("not")
+                    .padded()
+                    .ignore_then(negation)
+                    .map(|e| BooleanExpr::Not(Box::new(e)))
+                    .or(atom)
+            });
+            let conjunction = negation
+                .separated_by(just("&").or(keyword("and")))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .map(compound(Comb::And));
+            conjunction
+                .separated_by(just("|").or(keyword("or")))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .map(compound(Comb::Or))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn resolve(date: &str, now: NaiveDateTime) -> NaiveDateTime {
+        match Query::from_str(&format!("created > {date}"))
+            .unwrap()
+            .filter
+        {
+            Some(BooleanExpr::Comparison {
+                rhs: ValueExpr::Date(date),
+                ..
+            }) => date.resolve(now),
+            other => panic!("{date} parsed as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn relative_dates_resolve_against_now() {
+        // A Wednesday.
+        let now = at("2024-05-15 13:30");
+        for (date, expected) in [
+            ("now", "2024-05-15 13:30"),
+            ("today", "2024-05-15 00:00"),
+            ("yesterday", "2024-05-14 00:00"),
+            ("tomorrow", "2024-05-16 00:00"),
+            ("start_of_week", "2024-05-13 00:00"),
+            ("this month", "2024-05-01 00:00"),
+            ("last wednesday", "2024-05-08 00:00"),
+            ("last monday", "2024-05-13 00:00"),
+            ("-3d", "2024-05-12 13:30"),
+            ("+2w", "2024-05-29 13:30"),
+            ("-30m", "2024-05-15 13:00"),
+            ("2024-01-01 10:00", "2024-01-01 10:00"),
+        ] {
+            assert_eq!(resolve(date, now), at(expected), "{date}");
+        }
+    }
+
+    #[test]
+    fn offsets_stay_within_the_range_of_dates() {
+        let e = Query::from_str("created > -99999999w").unwrap_err();
+        assert_eq!(e.message, "duration too long");
+        assert!(Query::from_str("age > 99999999999999999999d").is_err());
+        let far = DateExpr::Offset(parser::MAX_DURATION);
+        assert_eq!(far.resolve(NaiveDateTime::MAX), NaiveDateTime::MAX);
+        let before = DateExpr::Offset(-parser::MAX_DURATION);
+        assert_eq!(before.resolve(NaiveDateTime::MIN), NaiveDateTime::MIN);
+    }
+
+    #[test]
+    fn baseline_dates_still_parse() {
+        let now = at("2024-05-15 13:30");
+        for date in [
+            "2024-01-01 10:00",
+            "2024-01-01  10:00",
+            "2024-01-01\t10:00",
+            "2024-01-0110:00",
+            "2024-01-01T10:00",
+        ] {
+            assert_eq!(resolve(date, now), at("2024-01-01 10:00"), "{date:?}");
+        }
+        assert_eq!(resolve("2024-01-01", now), at("2024-01-01 00:00"));
+    }
+
+    #[test]
+    fn baseline_chains_still_parse_the_same() {
+        let parsed = |filter: &str| format!("{:?}", Query::from_str(filter).unwrap().filter);
+        let compound = |combinator, exprs| BooleanExpr::Compound { combinator, exprs };
+        let boxed = |index| BooleanExpr::Box { index };
+        for (filter, expected) in [
+            (
+                "(completed & box[0])",
+                compound(Comb::And, vec![BooleanExpr::Completed, boxed(0)]),
+            ),
+            (
+                "(box[0] | box[1] | box[2])",
+                compound(Comb::Or, vec![boxed(0), boxed(1), boxed(2)]),
+            ),
+            (
+                "((box[0] | box[1]) & not completed)",
+                compound(
+                    Comb::And,
+                    vec![
+                        compound(Comb::Or, vec![boxed(0), boxed(1)]),
+                        BooleanExpr::Not(Box::new(BooleanExpr::Completed)),
+                    ],
+                ),
+            ),
+            (
+                "not (completed & box[-1])",
+                BooleanExpr::Not(Box::new(compound(
+                    Comb::And,
+                    vec![BooleanExpr::Completed, boxed(-1)],
+                ))),
+            ),
+        ] {
+            assert_eq!(parsed(filter), format!("{:?}", Some(expected)), "{filter}");
+        }
+    }
+
+    #[test]
+    fn tags_are_regexes_only_after_a_tilde() {
+        let tag = |filter: &str| match Query::from_str(filter).unwrap().filter {
+            Some(BooleanExpr::Tag(pattern)) => pattern,
+            other => panic!("{filter} parsed as {other:?}"),
+        };
+        assert!(matches!(tag("tag(/x)"), TagPattern::Name(name) if name == "/x"));
+        assert!(matches!(tag("tag(/x/*)"), TagPattern::Glob(_)));
+        assert!(matches!(tag("tag(~/^x$/)"), TagPattern::Regex(_)));
+        assert!(matches!(tag("tag(~ /^x$/)"), TagPattern::Regex(_)));
+        assert!(Query::from_str("tag(~/(/)").is_err());
+    }
+
+    #[test]
+    fn mismatched_comparisons_are_errors() {
+        for filter in ["count(checked) > 2h", "created > 5", "field(due) < 3d"] {
+            let e = Query::from_str(filter).unwrap_err();
+            assert!(e.message.starts_with("can't compare"), "{filter}: {e}");
+        }
+        let e = Query::from_str("completed & created > 5").unwrap_err();
+        assert_eq!(e.span.start, 12);
+        for filter in [
+            "field(due) < 2024-06-01",
+            "field(n) > 5",
+            "created < box[0]",
+        ] {
+            assert!(Query::from_str(filter).is_ok(), "{filter}");
+        }
+    }
+
+    fn titles(data: &FilteredData) -> Vec<&str> {
+        data.iter().map(|t| t.title()).collect()
+    }
+
+    /// Tasks with tags and a number of empty boxes, created a minute apart.
+    fn sortable(tasks: &[(&str, &[&str], usize)]) -> FilteredData {
+        let tasks = tasks
+            .iter()
+            .enumerate()
+            .map(|(i, (title, tags, boxes))| {
+                Task::new(
+                    title.to_string(),
+                    at("2024-05-01 09:00") + TimeDelta::minutes(i as i64),
+                    vec![BoxState::Empty; *boxes],
+                    tags.iter().map(|t| t.to_string()).collect(),
+                    Default::default(),
+                    None,
+                )
+            })
+            .collect();
+        FilteredData::new(Data::new(PathBuf::new(), tasks))
+    }
+
+    #[test]
+    fn sorting_keeps_ties_in_order() {
+        let mut data = sortable(&[("a", &[], 2), ("b", &[], 1), ("c", &[], 2), ("d", &[], 0)]);
+        data.set_filter("sort by count(boxes)").unwrap();
+        assert_eq!(titles(&data), ["d", "b", "a", "c"]);
+        data.set_filter("sort by count(boxes) desc").unwrap();
+        assert_eq!(titles(&data), ["a", "c", "b", "d"]);
+        data.set_filter("sort by count(boxes) desc, created desc")
+            .unwrap();
+        assert_eq!(titles(&data), ["c", "a", "b", "d"]);
+        // A change sorts the task again.
+        let d = data.get_id(3);
+        data.push_box(d);
+        data.push_box(d);
+        data.push_box(d);
+        assert_eq!(titles(&data), ["d", "c", "a", "b"]);
+    }
+
+    #[test]
+    fn groups_are_alphabetical_with_untagged_last() {
+        let mut data = sortable(&[
+            ("a", &[], 0),
+            ("b", &["work", "home"], 1),
+            ("c", &["work"], 0),
+            ("d", &["home"], 2),
+        ]);
+        data.set_filter("group by tag sort by count(boxes) desc")
+            .unwrap();
+        assert_eq!(titles(&data), ["d", "b", "c", "a"]);
+        let groups: Vec<_> = data
+            .groups()
+            .iter()
+            .map(|g| (g.name.as_str(), g.start, g.len))
+            .collect();
+        assert_eq!(
+            groups,
+            [("home", 0, 2), ("work", 2, 1), ("(untagged)", 3, 1)]
+        );
+    }
+
+    fn tag_matches(pattern: &str, tag: &str) -> bool {
+        match Query::from_str(&format!("tag({pattern})")).unwrap().filter {
+            Some(BooleanExpr::Tag(pattern)) => pattern.is_match(tag),
+            other => panic!("{pattern} parsed as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn tags_match_by_level() {
+        for (pattern, tag, expected) in [
+            // A parent matches its children, but not siblings sharing a prefix.
+            ("work", "work", true),
+            ("work", "work/a/b", true),
+            ("work", "workshop", false),
+            ("work/a", "work", false),
+            ("work/a", "work/ab", false),
+            // `*` matches exactly one level, or part of one.
+            ("work/*", "work/a", true),
+            ("work/*", "work", false),
+            ("work/*", "work/a/b", false),
+            ("*/a", "home/a", true),
+            ("work/client*", "work/clientA", true),
+            ("work/client*", "work/agency", false),
+            ("work/*A", "work/clientA", true),
+            ("work/c*t*A", "work/clientA", true),
+            // `**` matches any number of levels, none included.
+            ("work/**", "work", true),
+            ("work/**", "work/a/b/c", true),
+            ("work/**", "workshop/a", false),
+            ("work/**/bug", "work/bug", true),
+            ("work/**/bug", "work/a/b/bug", true),
+            ("work/**/bug", "work/a/bugs", false),
+            ("**/bug", "bug", true),
+            ("**/bug", "home/bug", true),
+        ] {
+            assert_eq!(tag_matches(pattern, tag), expected, "{pattern} vs {tag}");
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use eyre::{Context, Result, eyre};
+use serde::Serialize;
+
+use crate::storage::{BoxState, Event, EventKind, Task, format_date};
+
+/// Runs the configured shell command for each event in the background.
+pub struct Hooks {
+    commands: HashMap<EventKind, String>,
+    sender: Sender<eyre::Report>,
+    errors: Receiver<eyre::Report>,
+    running: Vec<JoinHandle<()>>,
+}
+
+/// What a hook is told about its event, as JSON on stdin and as environment
+/// variables.
+#[derive(Debug, Serialize)]
+struct Payload {
+    event: EventKind,
+    title: String,
+    tags: Vec<String>,
+    path: String,
+    created: String,
+    completed: Option<String>,
+    box_start: Option<String>,
+    box_end: Option<String>,
+    box_reason: Option<String>,
+}
+
+impl Hooks {
+    pub fn new(commands: HashMap<EventKind, String>) -> Self {
+        let (sender, errors) = mpsc::channel();
+        Self {
+            commands,
+            sender,
+            errors,
+            running: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, event: &Event, task: &Task, path: &Path) {
+        let Some(command) = self.commands.get(&event.kind) else {
+            return;
+        };
+        let command = command.clone();
+        let payload = Payload::new(event, task, path);
+        let sender = self.sender.clone();
+        log::info!("running {} hook for '{}'", event.kind, payload.title);
+        self.running.retain(|h| !h.is_finished());
+        self.running.push(thread::spawn(move || {
+            if let Err(e) = run_command(&command, &payload) {
+                // Nothing is listening once the app has quit.
+                let _ = sender.send(e.wrap_err(format!("Error running {} hook", payload.event)));
+            }
+        }));
+    }
+
+    /// Errors of hooks that failed since the last call.
+    pub fn errors(&self) -> Vec<eyre::Report> {
+        self.errors.try_iter().collect()
+    }
+
+    /// Waits for running hooks to finish, with the errors of those that
+    /// failed.
+    pub fn wait(mut self) -> Vec<eyre::Report> {
+        for handle in self.running.drain(..) {
+            if handle.join().is_err() {
+                log::error!("hook thread panicked");
+            }
+        }
+        self.errors()
+    }
+}
+
+fn run_command(command: &str, payload: &Payload) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(payload.env())
+        .stdin(Stdio::piped())
+        // Output would draw over the tui.
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .wrap_err_with(|| format!("spawning '{command}'"))?;
+    let json = serde_json::to_string(payload).wrap_err("serializing hook input")?;
+    if let Some(mut stdin) = child.stdin.take()
+        && let Err(e) = stdin.write_all(json.as_bytes())
+    {
+        // Hooks that don't read their input may exit before it is written.
+        log::warn!("writing input of '{command}': {e}");
+    }
+    let output = child
+        .wait_with_output()
+        .wrap_err_with(|| format!("waiting for '{command}'"))?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "'{command}' {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+impl Payload {
+    fn new(event: &Event, task: &Task, path: &Path) -> Self {
+        let (box_start, box_end, box_reason) = match &event.box_state {
+            Some(BoxState::Started(start)) => (*start, None, None),
+            Some(BoxState::Checked { start, end }) => (*start, Some(*end), None),
+            Some(BoxState::Voided { start, end, reason }) => (*start, Some(*end), reason.clone()),
+            Some(BoxState::Empty) | None => (None, None, None),
+        };
+        let mut tags: Vec<_> = task.tags().iter().cloned().collect();
+        tags.sort();
+        Self {
+            event: event.kind,
+            title: task.title().to_string(),
+            tags,
+            path: path.to_string_lossy().into_owned(),
+            created: format_date(task.created()),
+            completed: task.completed().as_ref().map(format_date),
+            box_start: box_start.as_ref().map(format_date),
+            box_end: box_end.as_ref().map(format_date),
+            box_reason,
+        }
+    }
+
+    /// Tags are joined by commas. Unset values are left out.
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("TASK_EVENT", self.event.to_string()),
+            ("TASK_TITLE", self.title.clone()),
+            ("TASK_TAGS", self.tags.join(",")),
+            ("TASK_PATH", self.path.clone()),
+            ("TASK_CREATED", self.created.clone()),
+        ];
+        let optional = [
+            ("TASK_COMPLETED", &self.completed),
+            ("BOX_START", &self.box_start),
+            ("BOX_END", &self.box_end),
+            ("BOX_REASON", &self.box_reason),
+        ];
+        env.extend(
+            optional
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, value.clone()?))),
+        );
+        env
+    }
+}
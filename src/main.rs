@@ -1,13 +1,22 @@
+mod cli;
 mod config;
 mod filter;
+mod hooks;
+mod report;
 mod storage;
 mod tui;
 
 use std::{
-    cell::RefCell, fs::create_dir_all, path::PathBuf, process::Command, rc::Rc, time::SystemTime,
+    cell::RefCell,
+    collections::HashMap,
+    fs::create_dir_all,
+    path::PathBuf,
+    process::{Command, ExitCode},
+    rc::Rc,
+    time::{Duration, SystemTime},
 };
 
-use chrono::{Datelike, Local};
+use chrono::{Datelike, Local, TimeDelta};
 use eyre::Context;
 use ratatui::{
     DefaultTerminal, Frame,
@@ -18,11 +27,17 @@ use ratatui::{
 use crate::{
     config::{Config, get_default_app_data_path},
     filter::FilteredData,
-    storage::Data,
+    hooks::Hooks,
+    storage::{Data, watch::DirWatcher},
     tui::{
         app::{AppTui, AppWidget},
-        popup::dialog::{AddDialog, ErrorDialog, SaveDialog},
+        history::{HISTORY_FILE, History},
+        popup::dialog::{
+            AddDialog, ConflictDialog, DeleteDialog, ErrorDialog, ReportDialog, SaveDialog, Stale,
+            StaleDialog, TrashDialog, VoidDialog,
+        },
         task::TaskFocus,
+        timer::TimerTui,
     },
 };
 
@@ -40,20 +55,21 @@ use crate::{
 //
 //
 
-fn main() {
+fn main() -> ExitCode {
     let args: Vec<_> = std::env::args().collect();
     if let Some(arg) = args.get(1) {
         match arg.as_str() {
             "-h" | "--help" => {
                 println!(
-                    "Usage: {} [options]\n\
+                    "Usage: {} [options | command]\n\
                      Run without options for tui.\n\n\
                      Options:\n\
                      \t-h, --help: print this help message\n\
                      \t-e, --edit: edit the config file\n\
-                     \t-p, --print: print the loaded configuration\n\
-                     ",
-                    args[0]
+                     \t-p, --print: print the loaded configuration\n\n\
+                     {}",
+                    args[0],
+                    cli::USAGE
                 );
             }
             "-e" | "--edit" => {
@@ -74,9 +90,22 @@ fn main() {
                 };
                 println!("{config:?}");
             }
-            &_ => println!("Unknown command. Run with --help for more options."),
+            command if !command.starts_with('-') => {
+                let config = match Config::load() {
+                    Ok(c) => c,
+                    Err((c, r)) => {
+                        eprintln!("failed to load config, continuing with default\n{r:?}");
+                        c
+                    }
+                };
+                return cli::run(&config, command, &args[2..]);
+            }
+            &_ => {
+                eprintln!("Unknown option. Run with --help for more options.");
+                return ExitCode::from(2);
+            }
         }
-        return;
+        return ExitCode::SUCCESS;
     }
 
     // Persist any config errors till we set up TUI.
@@ -94,10 +123,17 @@ fn main() {
     let terminal = ratatui::init();
     app.run(terminal, tui);
     ratatui::restore();
+    ExitCode::SUCCESS
 }
 
+/// How long to wait for input before checking for changes on disk and
+/// advancing the timer.
+const POLL_TIME: Duration = Duration::from_millis(250);
+
 pub struct App {
     data: FilteredData,
+    watcher: Option<DirWatcher>,
+    hooks: Hooks,
     exit: bool,
 }
 
@@ -105,6 +141,8 @@ impl Default for App {
     fn default() -> Self {
         Self {
             data: FilteredData::new(Data::new(get_default_app_data_path(), vec![])),
+            watcher: None,
+            hooks: Hooks::new(HashMap::new()),
             exit: false,
         }
     }
@@ -136,6 +174,12 @@ pub enum PopupEnum<'a> {
     WritePopup(SaveDialog),
     AddNew(AddDialog<'a>),
     Error(ErrorDialog),
+    Conflict(ConflictDialog),
+    Delete(DeleteDialog),
+    Trash(TrashDialog),
+    Void(VoidDialog<'a>),
+    Stale(StaleDialog),
+    Report(ReportDialog),
 }
 
 impl App {
@@ -143,12 +187,22 @@ impl App {
         config: &Config,
         mut reporting_err: Option<eyre::Report>,
     ) -> (Self, AppTui<'a>) {
-        let mut tui = AppTui::new(config.keybinds.clone());
-        let data = match Data::load(
-            shellexpand::tilde(&config.data_path.to_string_lossy())
-                .into_owned()
-                .into(),
-        ) {
+        let length = TimeDelta::minutes(config.pomodoro_minutes.into());
+        let timer = TimerTui::new(
+            length,
+            TimeDelta::minutes(config.break_minutes.into()),
+            config.auto_check,
+        );
+        let history = match History::load(config.data_dir().join(HISTORY_FILE)) {
+            Ok(h) => h,
+            Err((h, e)) => {
+                let e = e.wrap_err("Error loading filter history");
+                reporting_err = reporting_err.or(Some(e));
+                h
+            }
+        };
+        let mut tui = AppTui::new(config.keybinds.clone(), timer, history);
+        let mut data = match Data::load(config.data_dir()) {
             Ok(d) => d,
             Err((d, e)) => {
                 let e = e.wrap_err("Error loading data");
@@ -156,17 +210,54 @@ impl App {
                 d
             }
         };
+        data.set_backup_count(config.backup_count);
+        let watcher = match DirWatcher::new(data.source_dir()) {
+            Ok(w) => Some(w),
+            Err(e) => {
+                let e = e.wrap_err("Error watching data, changes on disk will not be reloaded");
+                reporting_err = reporting_err.or(Some(e));
+                None
+            }
+        };
         if let Some(e) = reporting_err {
             let error = format!("{:?}", e);
             log::error!("{e}");
             tui.set_error_focus(e);
             eprintln!("{error}");
         }
+        let now = Local::now().naive_local();
+        // Keep counting down a box that is still within its pomodoro.
+        if let Some((id, start)) = data
+            .started()
+            .filter(|(_, start)| *start + length >= now)
+            .max_by_key(|(_, start)| *start)
+        {
+            tui.resume_timer(id, start);
+        }
+        let stale: Vec<_> = data
+            .stale_started(now, length)
+            .into_iter()
+            .filter_map(|(id, start)| {
+                Some(Stale {
+                    id,
+                    title: data.get(id)?.title().to_string(),
+                    start,
+                })
+            })
+            .collect();
+        if !stale.is_empty() {
+            tui.queue_popup(PopupEnum::Stale(StaleDialog::new(stale, length)));
+        }
         let data = FilteredData::new(data);
         if !data.is_empty() {
             tui.set_table_index(0);
         }
-        let app: App = App { data, exit: false };
+        let app: App = App {
+            data,
+            watcher,
+            hooks: Hooks::new(config.hooks.clone()),
+            exit: false,
+        };
         (app, tui)
     }
 
@@ -178,17 +269,20 @@ impl App {
             terminal
                 .draw(|frame| self.draw(frame, tui.clone()))
                 .unwrap();
-            self.handle_events(tui);
+            self.handle_events(tui.clone());
             if self.exit {
                 break;
             }
+            self.reload_changed(tui.clone());
+            tui.borrow_mut().tick(&mut self.data);
+            self.run_hooks(tui);
         }
     }
     fn draw<'a>(&mut self, frame: &mut Frame, tui: Rc<RefCell<AppTui<'a>>>) {
         let mut cursor_buf_pos = None;
         let app_widget = AppWidget {
             app: tui.clone(),
-            data: &mut self.data,
+            data: &self.data,
             cursor_buf_pos: &mut cursor_buf_pos,
         };
         app_widget.render(frame.area(), frame.buffer_mut());
@@ -198,7 +292,47 @@ impl App {
         }
     }
 
+    fn reload_changed<'a>(&mut self, tui: Rc<RefCell<AppTui<'a>>>) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        let paths = watcher.changed_paths();
+        if paths.is_empty() {
+            return;
+        }
+        let mut tui = tui.borrow_mut();
+        match self.data.reload_paths(&paths) {
+            Ok(conflicts) => tui.queue_conflicts(conflicts),
+            Err((conflicts, e)) => {
+                log::error!("{e}");
+                tui.set_error_focus(e);
+                tui.queue_conflicts(conflicts);
+            }
+        }
+        tui.reloaded(&self.data);
+    }
+
+    /// Runs hooks of the events since the last call, and shows errors of
+    /// hooks that failed.
+    fn run_hooks<'a>(&mut self, tui: Rc<RefCell<AppTui<'a>>>) {
+        for event in self.data.take_events() {
+            let (Some(task), Some(path)) = (self.data.get(event.id), self.data.task_path(event.id))
+            else {
+                continue;
+            };
+            self.hooks.run(&event, task, &path);
+        }
+        let mut tui = tui.borrow_mut();
+        for e in self.hooks.errors() {
+            log::error!("{e}");
+            tui.queue_popup(PopupEnum::Error(ErrorDialog::from_error_focus(&e)));
+        }
+    }
+
     fn handle_events<'a>(&mut self, tui: Rc<RefCell<AppTui<'a>>>) {
+        if !event::poll(POLL_TIME).unwrap() {
+            return;
+        }
         match event::read().unwrap() {
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                 self.handle_key_event(tui, key_event)
@@ -0,0 +1,346 @@
+use std::{collections::HashMap, str::FromStr};
+
+use eyre::eyre;
+
+use crate::{
+    filter::FilteredData,
+    storage::{BoxState, Date, Task},
+};
+
+/// What checked boxes are summed by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Group {
+    /// Boxes count toward each tag of their task.
+    Tag,
+    Task,
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+    Month,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Csv,
+    Markdown,
+}
+
+/// Checked boxes and the minutes worked on them, summed by group.
+#[derive(Debug, Clone)]
+pub struct Report {
+    group: Group,
+    rows: Vec<Row>,
+    total: Row,
+}
+
+#[derive(Debug, Clone)]
+struct Row {
+    key: String,
+    boxes: usize,
+    /// Of the boxes that recorded when they were started.
+    minutes: i64,
+}
+
+/// Key for tasks without tags.
+const UNTAGGED: &str = "(untagged)";
+
+impl Group {
+    pub const ALL: [Group; 5] = [
+        Group::Tag,
+        Group::Task,
+        Group::Day,
+        Group::Week,
+        Group::Month,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::Tag => "tag",
+            Group::Task => "task",
+            Group::Day => "day",
+            Group::Week => "week",
+            Group::Month => "month",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|g| *g == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    fn is_period(self) -> bool {
+        matches!(self, Group::Day | Group::Week | Group::Month)
+    }
+
+    fn keys(self, task: &Task, end: &Date) -> Vec<String> {
+        match self {
+            Group::Tag if task.tags().is_empty() => vec![UNTAGGED.to_string()],
+            Group::Tag => task.tags().iter().cloned().collect(),
+            Group::Task => vec![task.title().to_string()],
+            Group::Day => vec![end.format("%Y-%m-%d").to_string()],
+            Group::Week => vec![end.format("%G-W%V").to_string()],
+            Group::Month => vec![end.format("%Y-%m").to_string()],
+        }
+    }
+}
+
+impl FromStr for Group {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|g| g.name() == s)
+            .ok_or_else(|| eyre!("unknown group '{s}', expected tag, task, day, week, or month"))
+    }
+}
+
+impl Row {
+    fn new(key: String) -> Self {
+        Self {
+            key,
+            boxes: 0,
+            minutes: 0,
+        }
+    }
+
+    fn add(&mut self, start: &Option<Date>, end: &Date) {
+        self.boxes += 1;
+        if let Some(start) = start {
+            self.minutes += (*end - *start).num_minutes();
+        }
+    }
+}
+
+impl Report {
+    /// Sums the boxes of visible tasks checked from `from` until, but not at,
+    /// `to`.
+    pub fn new(data: &FilteredData, group: Group, from: Option<Date>, to: Option<Date>) -> Self {
+        let mut rows: HashMap<String, Row> = HashMap::new();
+        let mut total = Row::new("total".to_string());
+        for task in data.iter() {
+            for b in task.boxes() {
+                let BoxState::Checked { start, end } = b else {
+                    continue;
+                };
+                if from.is_some_and(|from| *end < from) || to.is_some_and(|to| *end >= to) {
+                    continue;
+                }
+                total.add(start, end);
+                for key in group.keys(task, end) {
+                    rows.entry(key.clone())
+                        .or_insert_with(|| Row::new(key))
+                        .add(start, end);
+                }
+            }
+        }
+        let mut rows: Vec<_> = rows.into_values().collect();
+        if group.is_period() {
+            rows.sort_by(|a, b| a.key.cmp(&b.key));
+        } else {
+            rows.sort_by(|a, b| {
+                (b.boxes, b.minutes)
+                    .cmp(&(a.boxes, a.minutes))
+                    .then_with(|| a.key.cmp(&b.key))
+            });
+        }
+        Self { group, rows, total }
+    }
+
+    /// Each format ends with a total, apart from the rows so that it can't
+    /// be mistaken for a group named `total`. A box counts once toward it,
+    /// even if it is in several tag rows.
+    pub fn render(&self, format: Format) -> String {
+        let header = [self.group.name(), "boxes", "minutes"];
+        match format {
+            Format::Table => {
+                let cells: Vec<[String; 3]> = self
+                    .rows
+                    .iter()
+                    .chain([&self.total])
+                    .map(|r| [r.key.clone(), r.boxes.to_string(), r.minutes.to_string()])
+                    .collect();
+                let width = |i: usize| {
+                    cells
+                        .iter()
+                        .map(|c| c[i].chars().count())
+                        .chain([header[i].len()])
+                        .max()
+                        .unwrap()
+                };
+                let (key, boxes, minutes) = (width(0), width(1), width(2));
+                let mut out = format!(
+                    "{:key$}  {:>boxes$}  {:>minutes$}\n",
+                    header[0], header[1], header[2]
+                );
+                let total = cells.len() - 1;
+                for (i, [k, b, m]) in cells.into_iter().enumerate() {
+                    if i == total {
+                        out += &"-".repeat(key + boxes + minutes + 4);
+                        out += "\n";
+                    }
+                    out += &format!("{k:key$}  {b:>boxes$}  {m:>minutes$}\n");
+                }
+                out
+            }
+            Format::Csv => {
+                let mut out = header.join(",") + "\n";
+                for r in &self.rows {
+                    out += &format!("{},{},{}\n", csv_field(&r.key), r.boxes, r.minutes);
+                }
+                // A second table after a blank line.
+                out += &format!(
+                    "\ntotal boxes,total minutes\n{},{}\n",
+                    self.total.boxes, self.total.minutes
+                );
+                out
+            }
+            Format::Markdown => {
+                let mut out = format!(
+                    "| {} | {} | {} |\n| --- | ---: | ---: |\n",
+                    header[0], header[1], header[2]
+                );
+                for r in &self.rows {
+                    out += &format!(
+                        "| {} | {} | {} |\n",
+                        r.key.replace('|', "\\|"),
+                        r.boxes,
+                        r.minutes
+                    );
+                }
+                out += &format!(
+                    "\n**total**: {} boxes, {} minutes\n",
+                    self.total.boxes, self.total.minutes
+                );
+                out
+            }
+        }
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{NaiveDateTime, TimeDelta};
+
+    use super::*;
+    use crate::storage::Data;
+
+    fn at(date: &str) -> Date {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    /// Boxes of 25 minutes, ending at each of `ends`.
+    fn task(title: &str, tags: &[&str], ends: &[&str]) -> Task {
+        let boxes = ends
+            .iter()
+            .map(|end| BoxState::Checked {
+                start: Some(at(end) - TimeDelta::minutes(25)),
+                end: at(end),
+            })
+            .collect();
+        let tags = tags.iter().map(|t| t.to_string()).collect();
+        Task::new(
+            title.to_string(),
+            at("2024-05-01 09:00"),
+            boxes,
+            tags,
+            Default::default(),
+            None,
+        )
+    }
+
+    fn data() -> FilteredData {
+        FilteredData::new(Data::new(
+            PathBuf::new(),
+            vec![
+                // Friday, Sunday, and the Monday after.
+                task(
+                    "a",
+                    &["work", "home"],
+                    &["2024-05-31 10:00", "2024-06-02 23:30"],
+                ),
+                task("b", &["work"], &["2024-06-03 00:10"]),
+                task("c", &[], &["2024-05-31 12:00"]),
+            ],
+        ))
+    }
+
+    fn rows(report: &Report) -> Vec<(&str, usize, i64)> {
+        report
+            .rows
+            .iter()
+            .map(|r| (r.key.as_str(), r.boxes, r.minutes))
+            .collect()
+    }
+
+    #[test]
+    fn boxes_are_summed_by_the_day_and_week_they_ended() {
+        let data = data();
+        let report = Report::new(&data, Group::Day, None, None);
+        assert_eq!(
+            rows(&report),
+            [
+                ("2024-05-31", 2, 50),
+                ("2024-06-02", 1, 25),
+                ("2024-06-03", 1, 25)
+            ]
+        );
+        let report = Report::new(&data, Group::Week, None, None);
+        assert_eq!(rows(&report), [("2024-W22", 3, 75), ("2024-W23", 1, 25)]);
+        assert_eq!((report.total.boxes, report.total.minutes), (4, 100));
+    }
+
+    #[test]
+    fn tag_rows_overlap_but_the_total_does_not() {
+        let report = Report::new(&data(), Group::Tag, None, None);
+        assert_eq!(
+            rows(&report),
+            [("work", 3, 75), ("home", 2, 50), (UNTAGGED, 1, 25)]
+        );
+        assert_eq!(report.total.boxes, 4);
+    }
+
+    #[test]
+    fn range_includes_from_but_not_to() {
+        let report = Report::new(
+            &data(),
+            Group::Day,
+            Some(at("2024-05-31 12:00")),
+            Some(at("2024-06-03 00:00")),
+        );
+        assert_eq!(
+            rows(&report),
+            [("2024-05-31", 1, 25), ("2024-06-02", 1, 25)]
+        );
+    }
+
+    #[test]
+    fn totals_are_apart_from_the_rows() {
+        let report = Report::new(&data(), Group::Task, None, None);
+        assert_eq!(
+            report.render(Format::Csv),
+            "task,boxes,minutes\na,2,50\nb,1,25\nc,1,25\n\ntotal boxes,total minutes\n4,100\n"
+        );
+        assert!(
+            report
+                .render(Format::Markdown)
+                .ends_with("| c | 1 | 25 |\n\n**total**: 4 boxes, 100 minutes\n")
+        );
+        assert!(
+            report
+                .render(Format::Table)
+                .ends_with("c          1       25\n---------------------\ntotal      4      100\n")
+        );
+    }
+}
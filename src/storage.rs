@@ -1,46 +1,170 @@
 pub mod editing;
 pub mod keyboard_edit;
+mod merge;
+mod parser;
 mod span_edit;
 pub mod text_edit;
+pub mod watch;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
-    fs::{self, OpenOptions, create_dir_all},
+    fs::{self, File, OpenOptions, create_dir_all},
     io::{Read, Write},
-    path::PathBuf,
+    ops::Range,
+    path::{Path, PathBuf},
 };
 
-use chrono::{DateTime, Datelike, Local, NaiveDateTime};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeDelta};
 use chumsky::{Parser, error::Rich, span::Spanned};
 use crop::Rope;
 use eyre::{Context, OptionExt, Result, eyre};
+use serde::{Deserialize, Serialize};
 
 use crate::storage::{
     keyboard_edit::KeyboardEditable,
-    parser::{Field, Frontmatter, Value, box_field, date_field, rename_field, tag_field},
+    merge::merge3,
+    parser::{Field, Frontmatter, Value, box_field, date_field, write_double_quoted},
     text_edit::TextOp,
 };
 
+pub use parser::Node;
+
 pub type Date = NaiveDateTime;
 
+/// Folder under the data directory that deleted tasks are moved to.
+const TRASH_DIR: &str = ".trash";
+/// Names the folder each deletion is moved to within the trash.
+const TRASH_TIME_FORMAT: &str = "%Y-%m-%dT%H-%M-%S%.3f";
+
+/// What happened to the loaded tasks when a file changed on disk.
+pub enum Reload {
+    /// The file matches the task already loaded.
+    Unchanged,
+    Added,
+    Reloaded,
+    Removed,
+    /// The file changed, but its task has unsaved edits.
+    Conflict(Conflict),
+}
+
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub path: PathBuf,
+    /// `None` if the file was deleted.
+    pub theirs: Option<Box<Task>>,
+}
+
+/// A deleted task in the trash.
+#[derive(Debug, Clone)]
+pub struct Trashed {
+    pub title: String,
+    pub deleted: Date,
+    /// Where the file is in the trash.
+    pub path: PathBuf,
+    /// Where the file is restored to.
+    pub original: PathBuf,
+}
+
+pub enum Resolution {
+    KeepMine,
+    TakeTheirs,
+    /// Keep in-app metadata and merge the context bodies.
+    Merge,
+}
+
+/// Something done to a task that hooks can run on.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    BoxStarted,
+    BoxChecked,
+    BoxVoided,
+    TaskCompleted,
+    TaskCreated,
+    AfterSave,
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EventKind::BoxStarted => "box-started",
+            EventKind::BoxChecked => "box-checked",
+            EventKind::BoxVoided => "box-voided",
+            EventKind::TaskCompleted => "task-completed",
+            EventKind::TaskCreated => "task-created",
+            EventKind::AfterSave => "after-save",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub kind: EventKind,
+    pub id: TaskID,
+    /// The box that changed, for box events.
+    pub box_state: Option<BoxState>,
+}
+
+/// Identifies a task for as long as the app runs. IDs are never reused, so an
+/// ID kept for a removed task doesn't refer to another one.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub struct TaskID(u64);
+
 #[derive(Debug, Clone)]
 pub struct Data {
     source_dir: PathBuf,
-    tasks: Vec<Task>,
+    /// In the order tasks were added.
+    tasks: BTreeMap<TaskID, Task>,
+    next_id: u64,
+    backup_count: usize,
+    /// Events since they were last taken.
+    events: Vec<Event>,
 }
 
 impl Data {
     pub fn new(source_dir: PathBuf, tasks: Vec<Task>) -> Self {
-        Self { source_dir, tasks }
+        let mut out = Self {
+            source_dir,
+            tasks: BTreeMap::new(),
+            next_id: 0,
+            backup_count: 0,
+            events: Vec::new(),
+        };
+        for task in tasks {
+            out.insert(task);
+        }
+        out
+    }
+
+    fn insert(&mut self, task: Task) -> TaskID {
+        let id = TaskID(self.next_id);
+        self.next_id += 1;
+        self.tasks.insert(id, task);
+        id
+    }
+
+    /// Takes the events recorded since the last call.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn record(&mut self, kind: EventKind, id: TaskID, box_state: Option<BoxState>) {
+        self.events.push(Event {
+            kind,
+            id,
+            box_state,
+        });
+    }
+
+    /// Keep `count` previous versions of each file when overwriting it.
+    pub fn set_backup_count(&mut self, count: usize) {
+        self.backup_count = count;
     }
 
     /// Reports first error encountered.
     pub fn load(path: PathBuf) -> Result<Self, (Self, eyre::Report)> {
-        let mut out = Self {
-            source_dir: path.clone(),
-            tasks: vec![],
-        };
+        let mut out = Self::new(path.clone(), vec![]);
         let result = out.load_dir(path);
         result.map_err(|e| (out.clone(), e))?;
         Ok(out)
@@ -55,6 +179,10 @@ impl Data {
         let mut error = Ok(());
         for entry in read_dir {
             let file_type = entry.file_type()?;
+            // Skips the trash, among others.
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
             if file_type.is_dir() {
                 error = error.and(self.load_dir(entry.path()));
             }
@@ -74,88 +202,246 @@ impl Data {
                         continue;
                     }
                 };
-                self.tasks.push(task);
+                self.insert(task);
             }
         }
         error
     }
 
-    fn load_file(&mut self, path: PathBuf) -> Result<Task> {
+    fn load_file(&self, path: PathBuf) -> Result<Task> {
         let mut buf = String::new();
         OpenOptions::new()
             .read(true)
             .open(&path)?
             .read_to_string(&mut buf)?;
-        buf = buf.trim().to_owned();
-        buf += "\n";
+        // Keep the rest of the file as is, so it can be written back unchanged.
+        buf = buf.trim_start().to_owned();
         let metadata = fs::metadata(&path).wrap_err("reading metadata")?;
         let created = metadata.created().context("reading created time")?;
 
         Task::from_string(DateTime::<Local>::from(created).naive_local(), path, buf)
     }
 
+    /// Brings the task stored at `path` up to date with the file, which may
+    /// have been added, changed, or removed by another program.
+    pub fn reload_path(&mut self, path: &Path) -> Result<Reload> {
+        let id = self.find_stored_at(path);
+        let theirs = if path.exists() {
+            Some(self.load_file(path.to_path_buf())?)
+        } else {
+            None
+        };
+        Ok(match (id, theirs) {
+            (None, None) => Reload::Unchanged,
+            (None, Some(theirs)) => {
+                self.insert(theirs);
+                Reload::Added
+            }
+            // Likely our own write, even if the task was edited since.
+            (Some(id), Some(theirs)) if self.tasks[&id].is_stored_as(&theirs) => Reload::Unchanged,
+            (Some(id), None) if !self.tasks[&id].dirty => {
+                self.tasks.remove(&id);
+                Reload::Removed
+            }
+            (Some(id), Some(theirs)) if !self.tasks[&id].dirty => {
+                *self.task_mut(id) = theirs;
+                Reload::Reloaded
+            }
+            (Some(_), theirs) => Reload::Conflict(Conflict {
+                path: path.to_path_buf(),
+                theirs: theirs.map(Box::new),
+            }),
+        })
+    }
+
+    pub fn resolve_conflict(&mut self, conflict: Conflict, resolution: Resolution) {
+        let Some(id) = self.find_stored_at(&conflict.path) else {
+            return;
+        };
+        let mine = self.task_mut(id);
+        match (resolution, conflict.theirs) {
+            (Resolution::TakeTheirs, None) => {
+                self.tasks.remove(&id);
+            }
+            (Resolution::TakeTheirs, Some(theirs)) => *mine = *theirs,
+            (Resolution::Merge, Some(theirs)) => {
+                let base = mine.layout.as_ref().map(|l| l.body.as_str());
+                let merged = merge3(
+                    base.unwrap_or_default(),
+                    &mine.context.inner().to_string(),
+                    &theirs.context.inner().to_string(),
+                );
+                mine.context = KeyboardEditable::from_rope(merged.into(), true);
+                // Later writes and merges are relative to what is on disk now.
+                mine.layout = theirs.layout;
+                mine.dirty = true;
+            }
+            (Resolution::KeepMine, Some(theirs)) => {
+                mine.layout = theirs.layout;
+                mine.dirty = true;
+            }
+            (Resolution::KeepMine | Resolution::Merge, None) => mine.dirty = true,
+        }
+    }
+
     pub fn write_dirty(&mut self) -> Result<()> {
         self.fix_path_conflicts();
-        for index in 0..self.tasks.len() {
-            if self.tasks[index].dirty {
-                self.write_file(index)?;
-            }
+        let dirty: Vec<_> = self
+            .tasks
+            .iter()
+            .filter(|(_, t)| t.dirty)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in dirty {
+            self.write_file(id)?;
+            self.record(EventKind::AfterSave, id, None);
         }
         Ok(())
     }
 
     fn fix_path_conflicts(&mut self) {
-        let mut path_to_index: HashMap<_, Vec<_>> = HashMap::new();
-        for index in 0..self.tasks.len() {
-            let t = &self.tasks[index];
+        let mut path_to_ids: HashMap<_, Vec<_>> = HashMap::new();
+        for (id, t) in &self.tasks {
             let path = self.get_task_path(t);
-            let v = path_to_index.entry(path).or_default();
-            v.push(index);
+            let v = path_to_ids.entry(path).or_default();
+            v.push(*id);
         }
-        for (path, indices) in path_to_index {
-            if indices.len() < 2 {
+        let mut taken: HashSet<PathBuf> = path_to_ids.keys().cloned().collect();
+        for (path, ids) in path_to_ids {
+            if ids.len() < 2 {
                 continue;
             }
+            // The task already stored at the path keeps it, so that paths
+            // printed or linked to before stay valid.
+            let stored = ids
+                .iter()
+                .find(|id| self.tasks[id].source_path.as_ref() == Some(&path));
+            let moved: Vec<_> = ids.iter().filter(|id| Some(*id) != stored).collect();
             // Manually override source path to avoid path conflicts.
-            for (i, task_index) in indices.iter().enumerate() {
-                let t = &mut self.tasks[*task_index];
+            let mut suffix = 0;
+            for id in moved {
+                let mut free = path.clone();
+                loop {
+                    free.set_file_name(
+                        path.file_stem()
+                            .expect("should have had file name")
+                            .to_string_lossy()
+                            .into_owned()
+                            + &format!("_{suffix}.")
+                            + &path
+                                .extension()
+                                .map(|e| e.to_string_lossy().into_owned())
+                                .unwrap_or_default(),
+                    );
+                    suffix += 1;
+                    if !taken.contains(&free) && !free.exists() {
+                        break;
+                    }
+                }
+                taken.insert(free.clone());
+                let t = self.task_mut(*id);
+                if let Some(source_path) = t.source_path.take() {
+                    t.moved_from.get_or_insert(source_path);
+                }
                 // We're not overwriting data because rename would have already
                 // been used to set title.
                 t.rename = Some(t.title.clone());
-                let mut path = path.clone();
-                path.set_file_name(
-                    path.file_stem()
-                        .expect("should have had file name")
-                        .to_string_lossy()
-                        .into_owned()
-                        + &format!("_{i}.")
-                        + &path
-                            .extension()
-                            .map(|e| e.to_string_lossy().into_owned())
-                            .unwrap_or_default(),
-                );
-                t.source_path = Some(path);
+                t.source_path = Some(free);
                 t.dirty = true;
             }
         }
     }
 
-    fn write_file(&mut self, index: usize) -> Result<()> {
-        let task = &self.tasks[index];
+    /// Writes to a temporary file first and renames it over the original, so
+    /// the original is left intact if writing fails partway. The temporary
+    /// file is named after the process, so two instances don't write to the
+    /// same one.
+    fn write_file(&mut self, id: TaskID) -> Result<()> {
+        let task = &self.tasks[&id];
         let path = self.get_task_path(task);
+        let contents = task.to_string();
         let parent = path.parent().unwrap();
         create_dir_all(parent).wrap_err(format!("creating parent '{}'", parent.display()))?;
-        OpenOptions::new()
+        let file_name = path
+            .file_name()
+            .expect("task paths end in a file name")
+            .to_string_lossy();
+        let temp_path = parent.join(format!(".{file_name}.{}.tmp", std::process::id()));
+        let written = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(&path)
-            .wrap_err(format!("opening '{}'", path.display()))?
-            .write_all(task.to_string().as_bytes())?;
-        self.clear_dirty(index);
+            .open(&temp_path)
+            .wrap_err(format!("opening '{}'", temp_path.display()))
+            .and_then(|mut file| {
+                file.write_all(contents.as_bytes())?;
+                file.sync_all()?;
+                Ok(())
+            })
+            .wrap_err(format!("writing '{}'", temp_path.display()))
+            .and_then(|()| {
+                if self.backup_count > 0 && path.exists() {
+                    rotate_backups(&path, self.backup_count)?;
+                }
+                fs::rename(&temp_path, &path).wrap_err(format!(
+                    "renaming '{}' to '{}'",
+                    temp_path.display(),
+                    path.display()
+                ))
+            });
+        if let Err(e) = written {
+            if let Err(remove_err) = fs::remove_file(&temp_path) {
+                log::warn!("removing '{}': {remove_err}", temp_path.display());
+            }
+            return Err(e);
+        }
+        // Make the rename itself durable.
+        if let Err(e) = File::open(parent).and_then(|d| d.sync_all()) {
+            log::warn!("syncing directory '{}': {e}", parent.display());
+        }
+        let task = self.task_mut(id);
+        match Task::from_string(task.created, path.clone(), contents) {
+            Ok(written) => task.layout = written.layout,
+            Err(e) => log::warn!("re-reading written task: {e}"),
+        }
+        task.source_path = Some(path.clone());
+        self.clear_dirty(id);
+        // The task has been renamed, so its old file is stale, unless another
+        // task was renamed to it.
+        if let Some(old_path) = self.task_mut(id).moved_from.take()
+            && old_path != path
+            && !self.is_claimed(&old_path, id)
+            && old_path.exists()
+        {
+            if self.backup_count > 0 {
+                rotate_backups(&old_path, self.backup_count)?;
+            }
+            fs::remove_file(&old_path)
+                .wrap_err(format!("removing renamed '{}'", old_path.display()))?;
+        }
         Ok(())
     }
 
+    /// Whether a task other than `id` is stored at `path`.
+    fn is_claimed(&self, path: &Path, id: TaskID) -> bool {
+        self.tasks
+            .iter()
+            .any(|(other, t)| *other != id && self.get_task_path(t) == path)
+    }
+
+    /// The task stored at `path`, including tasks renamed since.
+    fn find_stored_at(&self, path: &Path) -> Option<TaskID> {
+        self.tasks
+            .iter()
+            .find(|(_, t)| self.get_task_path(t) == path || t.moved_from.as_deref() == Some(path))
+            .map(|(id, _)| *id)
+    }
+
+    /// Where the task is or will be stored.
+    pub fn task_path(&self, id: TaskID) -> Option<PathBuf> {
+        self.get(id).map(|t| self.get_task_path(t))
+    }
+
     fn get_task_path(&self, task: &Task) -> PathBuf {
         task.source_path.clone().unwrap_or_else(|| {
             self.source_dir
@@ -166,69 +452,286 @@ impl Data {
         })
     }
 
-    pub fn tasks(&self) -> &[Task] {
-        &self.tasks
+    pub fn source_dir(&self) -> &Path {
+        &self.source_dir
+    }
+
+    /// Tasks in the order they were added.
+    pub fn tasks(&self) -> impl Iterator<Item = (TaskID, &Task)> {
+        self.tasks.iter().map(|(id, t)| (*id, t))
+    }
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn get(&self, id: TaskID) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: TaskID) -> Option<&mut Task> {
+        self.tasks.get_mut(&id)
     }
 
-    pub fn tasks_mut(&mut self) -> &mut [Task] {
-        self.tasks.as_mut_slice()
+    fn task_mut(&mut self, id: TaskID) -> &mut Task {
+        self.tasks
+            .get_mut(&id)
+            .expect("task ids come from this data")
     }
 
-    fn set_dirty(&mut self, index: usize) {
-        self.tasks[index].dirty = true;
+    fn set_dirty(&mut self, id: TaskID) {
+        self.task_mut(id).dirty = true;
     }
 
-    fn clear_dirty(&mut self, index: usize) {
-        self.tasks[index].dirty = false;
+    fn clear_dirty(&mut self, id: TaskID) {
+        self.task_mut(id).dirty = false;
     }
 
-    pub fn set_completed(&mut self, index: usize, value: Option<Date>) {
-        self.set_dirty(index);
-        self.tasks[index].completed = value;
+    pub fn set_completed(&mut self, id: TaskID, value: Option<Date>) {
+        self.set_dirty(id);
+        self.task_mut(id).completed = value;
+        if value.is_some() {
+            self.record(EventKind::TaskCompleted, id, None);
+        }
     }
 
-    pub fn push_box(&mut self, index: usize) {
-        self.set_dirty(index);
-        self.tasks[index].boxes.push(BoxState::Empty);
+    pub fn push_box(&mut self, id: TaskID) {
+        self.set_dirty(id);
+        self.task_mut(id).boxes.push(BoxState::Empty);
     }
 
     /// Returns new state.
-    pub fn step_box_state(&mut self, index: usize, time: Date) -> Option<BoxState> {
-        self.set_dirty(index);
-        let last_mut = self.tasks[index]
+    pub fn step_box_state(&mut self, id: TaskID, time: Date) -> Option<BoxState> {
+        self.set_dirty(id);
+        let last_mut = self
+            .task_mut(id)
             .boxes
             .iter_mut()
-            .find(|b| !matches!(b, BoxState::Checked(_)))?;
-        *last_mut = match *last_mut {
-            BoxState::Empty => BoxState::Started,
-            BoxState::Started => BoxState::Checked(time),
-            last_mut => last_mut,
+            .find(|b| matches!(b, BoxState::Empty | BoxState::Started(_)))?;
+        let (kind, state) = match *last_mut {
+            BoxState::Started(start) => (
+                EventKind::BoxChecked,
+                BoxState::Checked { start, end: time },
+            ),
+            _ => (EventKind::BoxStarted, BoxState::Started(Some(time))),
         };
-        Some(*last_mut)
+        *last_mut = state.clone();
+        self.record(kind, id, Some(state.clone()));
+        Some(state)
+    }
+
+    /// Voids the started box. Returns whether there was one.
+    pub fn void_box(&mut self, id: TaskID, time: Date, reason: Option<String>) -> bool {
+        self.finish_started(id, EventKind::BoxVoided, |start| BoxState::Voided {
+            start,
+            end: time,
+            reason,
+        })
+    }
+
+    /// Checks the started box as finished at `end`. Returns whether there
+    /// was one.
+    pub fn check_started(&mut self, id: TaskID, end: Date) -> bool {
+        self.finish_started(id, EventKind::BoxChecked, |start| BoxState::Checked {
+            start,
+            end,
+        })
+    }
+
+    fn finish_started(
+        &mut self,
+        id: TaskID,
+        kind: EventKind,
+        finish: impl FnOnce(Option<Date>) -> BoxState,
+    ) -> bool {
+        let Some(started) = self
+            .task_mut(id)
+            .boxes
+            .iter_mut()
+            .find(|b| matches!(b, BoxState::Started(_)))
+        else {
+            return false;
+        };
+        let BoxState::Started(start) = *started else {
+            unreachable!("found a started box");
+        };
+        *started = finish(start);
+        let state = started.clone();
+        self.set_dirty(id);
+        self.record(kind, id, Some(state));
+        true
+    }
+
+    /// Tasks with a started box, with the box's start.
+    pub fn started(&self) -> impl Iterator<Item = (TaskID, Date)> {
+        self.tasks().filter_map(|(id, task)| {
+            task.boxes.iter().find_map(|b| match b {
+                BoxState::Started(Some(start)) => Some((id, *start)),
+                _ => None,
+            })
+        })
+    }
+
+    /// Tasks with a box started longer than `length` before `now`, likely
+    /// left running when the app last quit, with the box's start.
+    pub fn stale_started(&self, now: Date, length: TimeDelta) -> Vec<(TaskID, Date)> {
+        self.started()
+            .filter(|(_, start)| *start + length < now)
+            .collect()
     }
 
-    pub fn remove_empty_state(&mut self, index: usize) {
-        let Some(box_i) = self.tasks[index]
+    pub fn remove_empty_state(&mut self, id: TaskID) {
+        let Some(box_i) = self.tasks[&id]
             .boxes
             .iter()
             .rposition(|b| matches!(b, BoxState::Empty))
         else {
             return;
         };
-        self.set_dirty(index);
-        self.tasks[index].boxes.remove(box_i);
+        self.set_dirty(id);
+        self.task_mut(id).boxes.remove(box_i);
+    }
+
+    /// Renames the task. Its file is moved to match on the next write.
+    pub fn set_title(&mut self, id: TaskID, title: String) {
+        let task = self.task_mut(id);
+        if task.title == title {
+            return;
+        }
+        if let Some(source_path) = task.source_path.take() {
+            let file_name = format!("{}.md", urlencoding::encode(&title));
+            task.source_path = Some(source_path.with_file_name(file_name));
+            task.moved_from.get_or_insert(source_path);
+        }
+        // The file name holds the title now.
+        task.rename = None;
+        task.title = title;
+        self.set_dirty(id);
+    }
+
+    pub fn set_tags(&mut self, id: TaskID, tags: Vec<String>) {
+        self.task_mut(id).set_tags(tags);
+    }
+
+    pub fn push(&mut self, task: Task) -> TaskID {
+        let id = self.insert(task);
+        self.set_dirty(id);
+        self.record(EventKind::TaskCreated, id, None);
+        id
+    }
+
+    /// Moves the task into the trash, with any unsaved edits.
+    pub fn remove(&mut self, id: TaskID) -> Result<()> {
+        let task = &self.tasks[&id];
+        let path = self.get_task_path(task);
+        let relative = path
+            .strip_prefix(&self.source_dir)
+            .wrap_err_with(|| format!("'{}' is outside of the data folder", path.display()))?;
+        let trash_path = self
+            .source_dir
+            .join(TRASH_DIR)
+            .join(Local::now().format(TRASH_TIME_FORMAT).to_string())
+            .join(relative);
+        let parent = trash_path.parent().unwrap();
+        create_dir_all(parent).wrap_err(format!("creating parent '{}'", parent.display()))?;
+        fs::write(&trash_path, task.to_string())
+            .wrap_err(format!("writing '{}'", trash_path.display()))?;
+        // Backups go along, and come back when the task is restored.
+        if !self.is_claimed(&path, id) {
+            move_backups(&path, &trash_path)?;
+        }
+        for stale in [Some(&path), task.moved_from.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            if stale.exists() && !self.is_claimed(stale, id) {
+                fs::remove_file(stale).wrap_err(format!("removing '{}'", stale.display()))?;
+            }
+        }
+        self.tasks.remove(&id);
+        Ok(())
     }
 
-    pub fn set_tags(&mut self, index: usize, tags: Vec<String>) {
-        self.tasks[index].set_tags(tags);
+    /// Deleted tasks, most recently deleted first.
+    pub fn trash(&self) -> Result<Vec<Trashed>> {
+        let trash_dir = self.source_dir.join(TRASH_DIR);
+        if !trash_dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut out = vec![];
+        let read_dir = trash_dir
+            .read_dir()
+            .wrap_err_with(|| format!("listing '{}'", trash_dir.display()))?;
+        for entry in read_dir {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Ok(deleted) = NaiveDateTime::parse_from_str(&name, TRASH_TIME_FORMAT) else {
+                log::warn!("skipping unknown trash entry '{name}'");
+                continue;
+            };
+            let mut files = vec![];
+            find_task_files(&entry.path(), &mut files)?;
+            for path in files {
+                let relative = path.strip_prefix(entry.path()).expect("found under entry");
+                // `rename` may hold a title the file name can't.
+                let title = match self.load_file(path.clone()) {
+                    Ok(task) => task.title,
+                    Err(_) => Task::title_from_path(&path)?,
+                };
+                out.push(Trashed {
+                    title,
+                    deleted,
+                    original: self.source_dir.join(relative),
+                    path,
+                });
+            }
+        }
+        out.sort_by_key(|t| std::cmp::Reverse(t.deleted));
+        Ok(out)
     }
 
-    pub fn push(&mut self, task: Task) {
-        self.tasks.push(task);
-        self.set_dirty(self.tasks.len() - 1);
+    /// Moves a deleted task back to where it was.
+    pub fn restore(&mut self, trashed: &Trashed) -> Result<TaskID> {
+        if trashed.original.exists() {
+            return Err(eyre!("'{}' already exists", trashed.original.display()));
+        }
+        let parent = trashed.original.parent().unwrap();
+        create_dir_all(parent).wrap_err(format!("creating parent '{}'", parent.display()))?;
+        fs::rename(&trashed.path, &trashed.original).wrap_err(format!(
+            "moving '{}' to '{}'",
+            trashed.path.display(),
+            trashed.original.display()
+        ))?;
+        move_backups(&trashed.path, &trashed.original)?;
+        // Clean up the folders left empty in the trash.
+        let trash_dir = self.source_dir.join(TRASH_DIR);
+        for dir in trashed.path.ancestors().skip(1) {
+            if dir == trash_dir || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        let task = self
+            .load_file(trashed.original.clone())
+            .wrap_err_with(|| format!("reading '{}'", trashed.original.display()))?;
+        Ok(self.insert(task))
     }
 }
 
+fn find_task_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let read_dir = dir
+        .read_dir()
+        .wrap_err_with(|| format!("listing '{}'", dir.display()))?;
+    for entry in read_dir {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_task_files(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "md") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct Task {
     title: String,
@@ -239,8 +742,26 @@ pub struct Task {
     rename: Option<String>,
     context: KeyboardEditable,
     source_path: Option<PathBuf>,
+    /// Where the task was stored before being renamed, until it is written.
+    moved_from: Option<PathBuf>,
     dirty: bool,
     extra_fields: Vec<Field>,
+    layout: Option<Layout>,
+}
+
+/// Frontmatter as it was read, so that writing a task back only changes the
+/// bytes of fields that changed.
+#[derive(Debug, Clone)]
+struct Layout {
+    text: String,
+    /// Key and byte range in `text` of each field, in file order.
+    spans: Vec<(String, Range<usize>)>,
+    /// Fields as they were when read.
+    loaded: Vec<Field>,
+    /// Tags in the order they were read.
+    tag_order: Vec<String>,
+    /// Context as it was read, the base when merging outside edits.
+    body: String,
 }
 
 pub struct TaskEditableMut<'a> {
@@ -275,8 +796,10 @@ impl Task {
             context: KeyboardEditable::from_rope(context, true),
             completed,
             source_path: None,
+            moved_from: None,
             dirty: true,
             extra_fields: vec![],
+            layout: None,
         }
     }
 
@@ -295,6 +818,12 @@ impl Task {
     pub fn completed(&self) -> &Option<Date> {
         &self.completed
     }
+    /// Frontmatter fields without special meaning, in file order.
+    pub fn extra_fields(&self) -> impl Iterator<Item = (&str, Node)> {
+        self.extra_fields
+            .iter()
+            .map(|f| (f.key.as_str(), f.value.to_node()))
+    }
     pub fn editable(&self) -> &KeyboardEditable {
         &self.context
     }
@@ -313,15 +842,19 @@ impl Task {
         self.tags = tags.into_iter().collect();
     }
 
-    fn from_string(creation_date: Date, path: PathBuf, buf: String) -> Result<Self> {
-        let title = urlencoding::decode(
+    fn title_from_path(path: &Path) -> Result<String> {
+        Ok(urlencoding::decode(
             &path
                 .file_stem()
                 .ok_or_eyre("invalid name")?
                 .to_string_lossy(),
         )
         .wrap_err("decoding path")?
-        .to_string();
+        .to_string())
+    }
+
+    fn from_string(creation_date: Date, path: PathBuf, buf: String) -> Result<Self> {
+        let title = Self::title_from_path(&path)?;
 
         // Find front matter (wrapped by `---`).
         let mut line_offset = 0;
@@ -329,9 +862,20 @@ impl Task {
             .strip_prefix("---\n")
             .ok_or_eyre("missing frontmatter start marker")?;
         line_offset += 1;
-        let (front_matter, context) = buf
-            .split_once("---\n")
-            .ok_or_eyre("missing frontmatter end marker")?;
+        let (front_matter, context) = match buf
+            .match_indices("---\n")
+            .map(|(i, _)| i)
+            .find(|i| *i == 0 || buf[..*i].ends_with('\n'))
+        {
+            Some(end) => (&buf[..end], &buf[end + "---\n".len()..]),
+            // A file without a body may end right after the marker.
+            None => (
+                buf.strip_suffix("---")
+                    .filter(|f| f.is_empty() || f.ends_with('\n'))
+                    .ok_or_eyre("missing frontmatter end marker")?,
+                "",
+            ),
+        };
         let frontmatter = parser::parse_fields(front_matter, line_offset)?;
         let mut created = Ok(None);
         let mut boxes = Ok(None);
@@ -342,48 +886,102 @@ impl Task {
         fn format_error(
             name: &str,
             frontmatter: &Frontmatter,
-            field: &Spanned<(String, String)>,
-            errors: Vec<Rich<'_, char>>,
+            field: &Spanned<(String, Node)>,
+            reason: impl Display,
         ) -> eyre::Report {
-            let e = errors.first().unwrap();
             let (line, col) = frontmatter.get_location(field.span.start);
             let (end_line, end_col) = frontmatter.get_location(field.span.end);
-            eyre!("'{name}' failed to parse, {e} between {line}:{col} and {end_line}:{end_col}")
+            eyre!(
+                "'{name}' failed to parse, {reason} between {line}:{col} and {end_line}:{end_col}"
+            )
         }
         fn run_parser<'src, T>(
             parser: impl Parser<'src, &'src str, T, chumsky::extra::Err<Rich<'src, char>>>,
             key: &str,
             value: &'src str,
             frontmatter: &Frontmatter,
-            field: &Spanned<(String, String)>,
-        ) -> Result<Option<T>, eyre::Report> {
-            parser
-                .parse(value)
-                .into_result()
-                .map(Some)
-                .map_err(|e| format_error(key, frontmatter, field, e))
+            field: &Spanned<(String, Node)>,
+        ) -> Result<T, eyre::Report> {
+            parser.parse(value).into_result().map_err(|e| {
+                let e = e.first().unwrap();
+                format_error(key, frontmatter, field, e)
+            })
+        }
+        fn scalar<'a>(
+            key: &str,
+            frontmatter: &Frontmatter,
+            field: &'a Spanned<(String, Node)>,
+        ) -> Result<&'a str, eyre::Report> {
+            field
+                .1
+                .as_scalar()
+                .ok_or_else(|| format_error(key, frontmatter, field, "expected a single value"))
+        }
+        /// A single value is read as a list of one.
+        fn list<'a>(
+            key: &str,
+            frontmatter: &Frontmatter,
+            field: &'a Spanned<(String, Node)>,
+        ) -> Result<Vec<&'a str>, eyre::Report> {
+            match &field.1 {
+                Node::Null => Ok(vec![]),
+                Node::Scalar(s) => Ok(vec![&s.text]),
+                Node::Seq { items, .. } => items
+                    .iter()
+                    .map(|i| {
+                        i.as_scalar().ok_or_else(|| {
+                            format_error(key, frontmatter, field, "expected a list of values")
+                        })
+                    })
+                    .collect(),
+                Node::Map { .. } => Err(format_error(key, frontmatter, field, "expected a list")),
+            }
         }
 
         let mut remaining = vec![];
         for field in &frontmatter.parsed_fields {
             let key = field.0.as_str();
-            let value = field.1.clone();
+            let value = &field.1;
+            let fm = &frontmatter;
+            // Last wins.
             match key {
+                "created" | "completed" if matches!(value, Node::Null) => {}
                 "created" => {
-                    // Last wins.
-                    created = run_parser(date_field(), key, &value, &frontmatter, field);
+                    created = scalar(key, fm, field)
+                        .and_then(|v| run_parser(date_field(), key, v, fm, field))
+                        .map(Some)
                 }
-                "boxes" if value.trim().is_empty() => boxes = Ok(Some(vec![])),
-                "boxes" => boxes = run_parser(box_field(), key, &value, &frontmatter, field),
                 "completed" => {
-                    completed = run_parser(date_field(), key, &value, &frontmatter, field)
+                    completed = scalar(key, fm, field)
+                        .and_then(|v| run_parser(date_field(), key, v, fm, field))
+                        .map(Some)
+                }
+                "boxes" => {
+                    boxes = list(key, fm, field).and_then(|items| {
+                        items
+                            .into_iter()
+                            .map(|v| run_parser(box_field(), key, v.trim(), fm, field))
+                            .collect::<Result<Vec<_>>>()
+                            .map(Some)
+                    })
                 }
-                "tags" if value.trim().is_empty() => tags = Ok(Some(vec![])),
-                "tags" => tags = run_parser(tag_field(), key, &value, &frontmatter, field),
-                "rename" => rename = run_parser(rename_field(), key, &value, &frontmatter, field),
+                "tags" => {
+                    tags = list(key, fm, field).map(|items| {
+                        Some(
+                            items
+                                .into_iter()
+                                // Obsidian also accepts `tags: a, b`.
+                                .flat_map(|v| v.split(','))
+                                .map(|v| v.trim().to_string())
+                                .filter(|v| !v.is_empty())
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                }
+                "rename" => rename = scalar(key, fm, field).map(|v| Some(v.to_string())),
                 _ => remaining.push(Field {
                     key: key.into(),
-                    value: Value::Unknown(value),
+                    value: Value::Unknown(value.clone()),
                 }),
             }
         }
@@ -414,15 +1012,15 @@ impl Task {
             }
         };
 
-        let tags = match tags? {
-            Some(v) => v.into_iter().collect(),
+        let tag_order = match tags? {
+            Some(v) => v,
             None => {
                 dirty = true;
                 log::warn!(
                     "using empty tag list for {} (missing tags metadata)",
                     path.to_string_lossy()
                 );
-                HashSet::new()
+                vec![]
             }
         };
 
@@ -432,256 +1030,494 @@ impl Task {
             None => title,
         };
 
-        Ok(Self {
+        let mut task = Self {
             title,
             created,
             boxes,
             completed: completed?,
-            tags,
+            tags: tag_order.iter().cloned().collect(),
             rename,
             context: KeyboardEditable::from_rope(context.into(), true),
             source_path: Some(path),
+            moved_from: None,
             dirty,
             extra_fields: remaining,
-        })
+            layout: None,
+        };
+        task.layout = Some(Layout {
+            text: front_matter.to_string(),
+            spans: frontmatter
+                .parsed_fields
+                .iter()
+                .map(|f| (f.0.clone(), f.span.into_range()))
+                .collect(),
+            loaded: vec![],
+            tag_order,
+            body: context.to_string(),
+        });
+        let loaded = task.fields();
+        if let Some(layout) = &mut task.layout {
+            layout.loaded = loaded;
+        }
+        Ok(task)
+    }
+
+    /// Whether `other` was read from the text this task was last read from
+    /// or written as.
+    fn is_stored_as(&self, other: &Task) -> bool {
+        match (&self.layout, &other.layout) {
+            (Some(mine), Some(theirs)) => mine.text == theirs.text && mine.body == theirs.body,
+            _ => false,
+        }
+    }
+
+    /// Tags in the order they were read, followed by new ones.
+    fn tag_list(&self) -> Vec<String> {
+        let order = self.layout.as_ref().map(|l| l.tag_order.as_slice());
+        let mut out: Vec<String> = vec![];
+        for tag in order.unwrap_or_default() {
+            if self.tags.contains(tag) && !out.contains(tag) {
+                out.push(tag.clone());
+            }
+        }
+        let mut new: Vec<_> = self.tags.iter().filter(|t| !out.contains(t)).collect();
+        new.sort();
+        out.extend(new.into_iter().cloned());
+        out
+    }
+
+    /// Frontmatter fields in the order they are written for new files.
+    fn fields(&self) -> Vec<Field> {
+        let mut out = vec![Field::new("created", Value::Date(self.created))];
+        if let Some(completed) = self.completed {
+            out.push(Field::new("completed", Value::Date(completed)));
+        }
+        out.push(Field::new("boxes", Value::BoxList(self.boxes.clone())));
+        out.push(Field::new("tags", Value::TagList(self.tag_list())));
+        if let Some(rename) = &self.rename {
+            out.push(Field::new("rename", Value::Rename(rename.clone())));
+        }
+        out.extend(self.extra_fields.iter().cloned());
+        out
+    }
+}
+
+/// Shifts `name.md.1.bak` (newest) through `name.md.{count}.bak` up by one,
+/// dropping the oldest, and copies the current file into `name.md.1.bak`.
+fn rotate_backups(path: &Path, count: usize) -> Result<()> {
+    let backup = |n: usize| backup_path(path, n);
+    for n in (1..count).rev() {
+        let from = backup(n);
+        if from.exists() {
+            fs::rename(&from, backup(n + 1))
+                .wrap_err(format!("rotating backup '{}'", from.display()))?;
+        }
+    }
+    fs::copy(path, backup(1)).wrap_err(format!("backing up '{}'", path.display()))?;
+    Ok(())
+}
+
+/// `name.md.{n}.bak` for `name.md`.
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{n}.bak"));
+    path.with_file_name(name)
+}
+
+/// Moves the backups of `from` to be those of `to`.
+fn move_backups(from: &Path, to: &Path) -> Result<()> {
+    for n in 1.. {
+        let backup = backup_path(from, n);
+        if !backup.exists() {
+            break;
+        }
+        fs::rename(&backup, backup_path(to, n))
+            .wrap_err(format!("moving backup '{}'", backup.display()))?;
     }
+    Ok(())
 }
 
-fn format_date(date: &Date) -> String {
+pub fn format_date(date: &Date) -> String {
     date.format("%Y-%m-%dT%H:%M:%S").to_string()
 }
 
+/// Reads a date as written in frontmatter, with or without a time.
+pub fn parse_date(text: &str) -> Option<Date> {
+    date_field().parse(text).into_result().ok()
+}
+
 impl Display for Task {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "---")?;
-        writeln!(f, "created: {}", Value::Date(self.created))?;
-        if let Some(completed) = self.completed {
-            writeln!(f, "completed: {}", Value::Date(completed))?;
-        }
-        write!(f, "boxes:{}", Value::BoxList(self.boxes.clone()))?;
-        write!(
-            f,
-            "tags:{}",
-            Value::TagList(self.tags.iter().cloned().collect())
-        )?;
-        if let Some(rename) = &self.rename {
-            writeln!(f, "rename: {}", Value::Rename(rename.clone()))?;
+        let fields = self.fields();
+        let Some(layout) = &self.layout else {
+            for field in &fields {
+                write!(f, "{field}")?;
+            }
+            writeln!(f, "---")?;
+            return write!(f, "{}", self.context.inner());
+        };
+
+        // Only the last of repeated keys is read, so only it can change.
+        let last = |key: &str, fields: &[Field]| fields.iter().rposition(|f| f.key == key);
+        let mut written = vec![false; fields.len()];
+        let mut pos = 0;
+        for (i, (key, span)) in layout.spans.iter().enumerate() {
+            // Comments and blank lines between fields.
+            f.write_str(&layout.text[pos..span.start])?;
+            pos = span.end;
+            let original = &layout.text[span.clone()];
+            if layout.spans[i + 1..].iter().any(|(k, _)| k == key) {
+                // Unknown keys keep every occurrence as a field of its own.
+                let nth = layout.spans[..i].iter().filter(|(k, _)| k == key).count();
+                let same_key = fields.iter().enumerate().filter(|(_, f)| &f.key == key);
+                if let Some((index, _)) = same_key.clone().nth(nth)
+                    && same_key.count() > nth + 1
+                {
+                    written[index] = true;
+                }
+                f.write_str(original)?;
+                continue;
+            }
+            let Some(index) = last(key, &fields) else {
+                // Field was removed.
+                continue;
+            };
+            written[index] = true;
+            let unchanged = last(key, &layout.loaded)
+                .is_some_and(|l| layout.loaded[l].value == fields[index].value);
+            if unchanged {
+                f.write_str(original)?;
+            } else {
+                write!(f, "{}", fields[index])?;
+            }
         }
-        for field in &self.extra_fields {
-            writeln!(f, "{}: {}", field.key, field.value)?;
+        f.write_str(&layout.text[pos..])?;
+        for (field, written) in fields.iter().zip(written) {
+            if !written {
+                write!(f, "{field}")?;
+            }
         }
         writeln!(f, "---")?;
-        writeln!(f, "{}", self.context.inner())?;
-        Ok(())
+        write!(f, "{}", self.context.inner())
     }
 }
 
-mod parser {
-    use std::fmt::Display;
-
-    use chrono::NaiveDateTime;
-    use chumsky::{
-        prelude::*,
-        text::{Char, digits, ident, inline_whitespace, newline},
-    };
-    use eyre::eyre;
-
-    use crate::storage::{BoxState, Date, format_date};
-
-    #[derive(Debug, Clone)]
-    pub struct Field {
-        pub key: String,
-        pub value: Value,
-    }
+/// Boxes written before start times were recorded have no `start`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoxState {
+    Checked {
+        start: Option<Date>,
+        end: Date,
+    },
+    Started(Option<Date>),
+    /// Interrupted before it was finished, so it doesn't count as worked.
+    Voided {
+        start: Option<Date>,
+        end: Date,
+        reason: Option<String>,
+    },
+    Empty,
+}
 
-    #[derive(Debug, Clone)]
-    pub enum Value {
-        Unknown(String),
-        Date(Date),
-        BoxList(Vec<BoxState>),
-        TagList(Vec<String>),
-        Rename(String),
+impl BoxState {
+    /// When work on the box started and ended, `now` if it is still going.
+    pub fn worked(&self, now: Date) -> Option<(Date, Date)> {
+        match *self {
+            BoxState::Checked {
+                start: Some(start),
+                end,
+            } => Some((start, end)),
+            BoxState::Started(Some(start)) => Some((start, now)),
+            _ => None,
+        }
     }
+}
 
-    impl Display for Value {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match self {
-                Value::Unknown(s) => f.write_str(s),
-                Value::Date(naive_date_time) => {
-                    write!(f, "{}", format_date(naive_date_time))
-                }
-                Value::BoxList(box_states) => {
-                    writeln!(f)?;
-                    for b in box_states {
-                        writeln!(f, "  - {}", b)?;
-                    }
-                    Ok(())
+impl Display for BoxState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoxState::Checked {
+                start: Some(start),
+                end,
+            } => write!(f, "Checked({}, {})", format_date(start), format_date(end)),
+            BoxState::Checked { start: None, end } => write!(f, "Checked({})", format_date(end)),
+            BoxState::Started(Some(start)) => write!(f, "Started({})", format_date(start)),
+            BoxState::Started(None) => write!(f, "Started"),
+            BoxState::Voided { start, end, reason } => {
+                write!(f, "Voided(")?;
+                if let Some(start) = start {
+                    write!(f, "{}, ", format_date(start))?;
                 }
-                Value::TagList(items) => {
-                    writeln!(f)?;
-                    for t in items {
-                        writeln!(f, "  - {}", t)?;
-                    }
-                    Ok(())
+                write!(f, "{}", format_date(end))?;
+                if let Some(reason) = reason {
+                    write!(f, ", ")?;
+                    write_double_quoted(f, reason)?;
                 }
-                Value::Rename(t) => write!(f, "{}", t),
+                write!(f, ")")
             }
+            BoxState::Empty => write!(f, "Empty"),
         }
     }
+}
 
-    pub struct Frontmatter {
-        line_offset: usize,
-        text: String,
-        pub parsed_fields: Vec<Spanned<(String, String)>>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(title: &str) -> Task {
+        let created = NaiveDateTime::parse_from_str("2024-05-01 09:00", "%Y-%m-%d %H:%M").unwrap();
+        Task::new(
+            title.to_string(),
+            created,
+            vec![],
+            HashSet::new(),
+            Rope::new(),
+            None,
+        )
     }
-    impl Frontmatter {
-        pub fn get_location(&self, byte: usize) -> (usize, usize) {
-            let s = self.text.split_at(byte).0;
-            let out = (
-                self.line_offset + s.lines().count(),
-                1 + s.lines().last().map(|l| l.chars().count()).unwrap_or(0),
-            );
-            // instead of reporting the last character of a line, report the next line.
-            if s.ends_with(|s: char| s.is_newline()) && self.text.lines().count() > out.0 {
-                return (out.0 + 1, 0);
-            }
-            out
-        }
+
+    fn titles(dir: &Path) -> Vec<String> {
+        let data = Data::load(dir.to_path_buf()).map_err(|(_, e)| e).unwrap();
+        let mut titles: Vec<_> = data.tasks().map(|(_, t)| t.title().to_string()).collect();
+        titles.sort();
+        titles
     }
 
-    pub fn parse_fields(
-        frontmatter: &str,
-        line_offset: usize,
-    ) -> Result<Frontmatter, eyre::Report> {
-        let mut out = Frontmatter {
-            line_offset,
-            text: frontmatter.to_string(),
-            parsed_fields: vec![],
-        };
+    #[test]
+    fn unchanged_task_is_written_as_read() {
+        let text = "---\n# kept\ncreated: 2024-05-01T09:00:00\nboxes:\n- Checked(2024-05-01T09:00:00, 2024-05-01T09:25:00)\ntags: [b, a]\n\"quoted key\": 'single'  # note\nnotes: |\n  line\n    indented\nlinks: {a: 1, b: [x, y]}\n---\n# title\nbody\n";
+        let created = NaiveDateTime::parse_from_str("2024-05-01 09:00", "%Y-%m-%d %H:%M").unwrap();
+        let task = Task::from_string(created, PathBuf::from("title.md"), text.to_string()).unwrap();
+        assert_eq!(task.to_string(), text);
+    }
 
-        out.parsed_fields = field()
-            .spanned()
-            .repeated()
-            .collect::<Vec<_>>()
-            .padded()
-            .parse(frontmatter)
-            .into_result()
-            .map_err(|e| {
-                let Some(e) = e.first() else {
-                    return eyre!("missing error");
-                };
-                let (start, _end) = (e.span().start, e.span().end);
-                let (line, col) = out.get_location(start);
-                eyre!("parsing fields. {} at {line}:{col}", e.reason())
-            })?;
+    /// The boxes of `task`, written out and read back.
+    fn reread_boxes(boxes: Vec<BoxState>) -> Vec<BoxState> {
+        let mut task = task("foo");
+        task.boxes = boxes;
+        let text = task.to_string();
+        Task::from_string(task.created, PathBuf::from("foo.md"), text.clone())
+            .unwrap_or_else(|e| panic!("{e:?}\n{text}"))
+            .boxes
+    }
 
-        Ok(out)
+    #[test]
+    fn started_boxes_keep_their_start() {
+        let start = task("foo").created;
+        let boxes = vec![BoxState::Started(Some(start)), BoxState::Started(None)];
+        assert_eq!(reread_boxes(boxes.clone()), boxes);
     }
 
-    fn field<'src>() -> impl Parser<'src, &'src str, (String, String), extra::Err<Rich<'src, char>>>
-    {
-        let any_field = line()
-            .then(
-                just("  ")
-                    .map(|s| s.to_owned())
-                    .then(line())
-                    .repeated()
-                    .collect::<Vec<(String, String)>>(),
-            )
-            .map(|(first_line, lines)| {
-                first_line
-                    + "\n"
-                    + &lines
-                        .into_iter()
-                        .map(|(whitespace, line)| whitespace + &line + "\n")
-                        .collect::<String>()
-            });
+    #[test]
+    fn voided_boxes_keep_their_times_and_reason() {
+        let start = task("foo").created;
+        let end = start + TimeDelta::minutes(10);
+        let boxes = vec![
+            BoxState::Voided {
+                start: Some(start),
+                end,
+                reason: Some("call: \"urgent\" #phone, it's\nover".to_string()),
+            },
+            BoxState::Voided {
+                start: Some(start),
+                end,
+                reason: None,
+            },
+            BoxState::Voided {
+                start: None,
+                end,
+                reason: Some(String::new()),
+            },
+        ];
+        assert_eq!(reread_boxes(boxes.clone()), boxes);
+    }
 
-        ident()
-            .then_ignore(just(":"))
-            .then_ignore(inline_whitespace())
-            .then(any_field)
-            .map(|(key, value): (&str, _)| (key.to_string(), value))
-    }
-    fn line<'src>() -> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>> {
-        any()
-            .filter(|c: &char| !c.is_newline())
-            .repeated()
-            .collect::<String>()
-            .then_ignore(newline())
-    }
-    fn date<'src>() -> impl Parser<'src, &'src str, NaiveDateTime, extra::Err<Rich<'src, char>>> {
-        digits(10)
-            .exactly(4)
-            .then(just("-"))
-            .then(digits(10).exactly(2))
-            .then(just("-"))
-            .then(digits(10).exactly(2))
-            .then(just("T"))
-            .then(digits(10).exactly(2))
-            .then(just(":"))
-            .then(digits(10).exactly(2))
-            .then(just(":"))
-            .then(digits(10).exactly(2))
-            .to_slice()
-            .try_map(|t: &str, span| {
-                NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%S")
-                    .map_err(|e| Rich::custom(span, e))
-            })
+    /// Two tasks renamed in a chain, `baz` taking the file `foo` left. `baz`
+    /// is written first.
+    fn renamed_chain() -> (tempfile::TempDir, Data, TaskID, TaskID) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = Data::new(dir.path().to_path_buf(), vec![]);
+        let b = data.push(task("baz"));
+        let a = data.push(task("foo"));
+        data.write_dirty().unwrap();
+        data.set_title(a, "bar".to_string());
+        data.set_title(b, "foo".to_string());
+        (dir, data, a, b)
     }
-    pub fn date_field<'src>()
-    -> impl Parser<'src, &'src str, NaiveDateTime, extra::Err<Rich<'src, char>>> {
-        date().then_ignore(newline())
-    }
-    pub fn box_field<'src>()
-    -> impl Parser<'src, &'src str, Vec<BoxState>, extra::Err<Rich<'src, char>>> {
-        newline().ignore_then(
-            just("  - ")
-                .ignore_then(choice((
-                    just("Started").to(BoxState::Started),
-                    just("Empty").to(BoxState::Empty),
-                    just("Checked")
-                        .ignore_then(just("("))
-                        .ignore_then(date())
-                        .then_ignore(just(")"))
-                        .map(BoxState::Checked),
-                )))
-                .then_ignore(newline())
-                .repeated()
-                .at_least(1)
-                .collect::<Vec<_>>(),
-        )
+
+    #[test]
+    fn rename_into_renamed_away_path_keeps_both() {
+        let (dir, mut data, _, _) = renamed_chain();
+        data.write_dirty().unwrap();
+        assert_eq!(titles(dir.path()), ["bar", "foo"]);
     }
-    pub fn tag_field<'src>()
-    -> impl Parser<'src, &'src str, Vec<String>, extra::Err<Rich<'src, char>>> {
-        newline().ignore_then(
-            just("  - ")
-                .ignore_then(line())
-                .repeated()
-                .collect::<Vec<_>>(),
-        )
+
+    #[test]
+    fn remove_keeps_path_renamed_into() {
+        let (dir, mut data, a, b) = renamed_chain();
+        data.write_file(b).unwrap();
+        data.remove(a).unwrap();
+        assert_eq!(titles(dir.path()), ["foo"]);
     }
-    pub fn rename_field<'src>() -> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>>
-    {
-        line()
+
+    #[test]
+    fn failed_rename_removes_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = Data::new(dir.path().to_path_buf(), vec![]);
+        let id = data.push(task("foo"));
+        // A file can't be renamed over a directory that isn't empty.
+        let path = data.task_path(id).unwrap();
+        fs::create_dir_all(path.join("taken")).unwrap();
+        assert!(data.write_file(id).is_err());
+        let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, [path.file_name().unwrap()]);
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum BoxState {
-    Checked(Date),
-    Started,
-    Empty,
-}
+    /// A task written to disk, whose file is then changed by `external`.
+    fn reloaded(edit_mine: bool, external: bool) -> (tempfile::TempDir, Data, TaskID, Reload) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = Data::new(dir.path().to_path_buf(), vec![]);
+        let id = data.push(task("foo"));
+        data.write_dirty().unwrap();
+        let path = data.task_path(id).unwrap();
+        if edit_mine {
+            data.set_tags(id, vec!["mine".to_string()]);
+        }
+        if external {
+            let text = fs::read_to_string(&path).unwrap();
+            fs::write(&path, text + "theirs\n").unwrap();
+        }
+        let reload = data.reload_path(&path).unwrap();
+        (dir, data, id, reload)
+    }
 
-impl Display for BoxState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            BoxState::Checked(date_time) => {
-                f.write_fmt(core::format_args!("Checked({})", format_date(date_time)))
+    #[test]
+    fn own_write_is_not_a_conflict() {
+        let (_dir, data, id, reload) = reloaded(true, false);
+        assert!(matches!(reload, Reload::Unchanged));
+        let task = data.get(id).unwrap();
+        assert!(task.dirty() && task.tags().contains("mine"));
+    }
+
+    #[test]
+    fn external_change_is_reloaded() {
+        let (_dir, data, id, reload) = reloaded(false, true);
+        assert!(matches!(reload, Reload::Reloaded));
+        let body = data.get(id).unwrap().editable().inner().to_string();
+        assert!(body.ends_with("theirs\n"), "{body:?}");
+    }
+
+    #[test]
+    fn external_change_to_edited_task_is_a_conflict() {
+        let (_dir, data, id, reload) = reloaded(true, true);
+        assert!(matches!(reload, Reload::Conflict(_)));
+        assert!(data.get(id).unwrap().tags().contains("mine"));
+    }
+
+    #[test]
+    fn same_title_as_a_stored_task_takes_a_free_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = Data::new(dir.path().to_path_buf(), vec![]);
+        let first = data.push(task("foo"));
+        data.write_dirty().unwrap();
+        let path = data.task_path(first).unwrap();
+        // Taken by a file that isn't a task.
+        fs::write(path.with_file_name("foo_0.md"), "not a task").unwrap();
+        let second = data.push(task("foo"));
+        data.write_dirty().unwrap();
+        assert_eq!(data.task_path(first), Some(path.clone()));
+        assert_eq!(
+            data.task_path(second),
+            Some(path.with_file_name("foo_1.md"))
+        );
+        assert_eq!(
+            fs::read_to_string(path.with_file_name("foo_0.md")).unwrap(),
+            "not a task"
+        );
+    }
+
+    /// Files under `dir`, relative to it, sorted.
+    fn files(dir: &Path) -> Vec<String> {
+        let mut out = vec![];
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(d) = dirs.pop() {
+            for entry in fs::read_dir(d).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let relative = path.strip_prefix(dir).unwrap();
+                    out.push(relative.to_string_lossy().into_owned());
+                }
             }
-            BoxState::Started => write!(f, "Started"),
-            BoxState::Empty => write!(f, "Empty"),
         }
+        out.sort();
+        out
+    }
+
+    /// A task written three times, so with two backups, then deleted.
+    fn trashed() -> (tempfile::TempDir, Data, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = Data::new(dir.path().to_path_buf(), vec![]);
+        data.set_backup_count(2);
+        let id = data.push(task("foo"));
+        data.write_dirty().unwrap();
+        for tag in ["a", "b"] {
+            data.set_tags(id, vec![tag.to_string()]);
+            data.write_dirty().unwrap();
+        }
+        let path = data.task_path(id).unwrap();
+        data.remove(id).unwrap();
+        (dir, data, path)
+    }
+
+    #[test]
+    fn delete_moves_file_and_backups_to_trash() {
+        let (dir, data, path) = trashed();
+        let relative = path.strip_prefix(dir.path()).unwrap().to_string_lossy();
+        let files = files(dir.path());
+        assert_eq!(files.len(), 3, "{files:?}");
+        for (file, suffix) in files.iter().zip(["", ".1.bak", ".2.bak"]) {
+            assert!(file.starts_with(TRASH_DIR), "{file}");
+            assert!(file.ends_with(&format!("{relative}{suffix}")), "{file}");
+        }
+        let trash = data.trash().unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].title, "foo");
+        assert_eq!(trash[0].original, path);
+    }
+
+    #[test]
+    fn restore_moves_file_and_backups_back() {
+        let (dir, mut data, path) = trashed();
+        let trashed = data.trash().unwrap().remove(0);
+        let id = data.restore(&trashed).unwrap();
+        assert_eq!(data.task_path(id), Some(path.clone()));
+        assert!(data.get(id).unwrap().tags().contains("b"));
+        let relative = path.strip_prefix(dir.path()).unwrap().to_string_lossy();
+        let restored: Vec<_> = ["", ".1.bak", ".2.bak"]
+            .iter()
+            .map(|suffix| format!("{relative}{suffix}"))
+            .collect();
+        assert_eq!(files(dir.path()), restored);
+        assert!(data.trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn restore_keeps_a_task_stored_at_the_path_since() {
+        let (_dir, mut data, path) = trashed();
+        let id = data.push(task("foo"));
+        data.write_dirty().unwrap();
+        assert_eq!(data.task_path(id), Some(path.clone()));
+        let written = fs::read_to_string(&path).unwrap();
+        let trashed = data.trash().unwrap().remove(0);
+        assert!(data.restore(&trashed).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), written);
+        assert!(trashed.path.exists());
+        assert_eq!(data.task_count(), 1);
     }
 }
@@ -0,0 +1,119 @@
+/// Line based three-way merge. Where both sides changed the same lines
+/// differently, both versions are kept between conflict markers.
+pub fn merge3(base: &str, mine: &str, theirs: &str) -> String {
+    let base: Vec<_> = base.split_inclusive('\n').collect();
+    let mine: Vec<_> = mine.split_inclusive('\n').collect();
+    let theirs: Vec<_> = theirs.split_inclusive('\n').collect();
+    let mine_match = match_lines(&base, &mine);
+    let theirs_match = match_lines(&base, &theirs);
+
+    let mut out = String::new();
+    let (mut b, mut m, mut t) = (0, 0, 0);
+    for base_i in 0..base.len() {
+        // Lines unchanged on both sides split the text into chunks.
+        let (Some(mine_i), Some(theirs_i)) = (mine_match[base_i], theirs_match[base_i]) else {
+            continue;
+        };
+        merge_chunk(
+            &mut out,
+            &base[b..base_i],
+            &mine[m..mine_i],
+            &theirs[t..theirs_i],
+        );
+        out += base[base_i];
+        (b, m, t) = (base_i + 1, mine_i + 1, theirs_i + 1);
+    }
+    merge_chunk(&mut out, &base[b..], &mine[m..], &theirs[t..]);
+    out
+}
+
+fn merge_chunk(out: &mut String, base: &[&str], mine: &[&str], theirs: &[&str]) {
+    if mine == base || mine == theirs {
+        out.extend(theirs.iter().copied());
+    } else if theirs == base {
+        out.extend(mine.iter().copied());
+    } else {
+        let mut push_side = |marker: &str, lines: &[&str]| {
+            *out += marker;
+            out.extend(lines.iter().copied());
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+        };
+        push_side("<<<<<<< mine\n", mine);
+        push_side("=======\n", theirs);
+        *out += ">>>>>>> theirs\n";
+    }
+}
+
+/// For each line of `base`, the line of `other` it is matched with in a
+/// longest common subsequence.
+fn match_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    // lengths[i][j] is the LCS length of base[i..] and other[j..].
+    let mut lengths = vec![vec![0usize; other.len() + 1]; base.len() + 1];
+    for i in (0..base.len()).rev() {
+        for j in (0..other.len()).rev() {
+            lengths[i][j] = if base[i] == other[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut out = vec![None; base.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < base.len() && j < other.len() {
+        if base[i] == other[j] {
+            out[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_to_different_lines_both_apply() {
+        let base = "a\nb\nc\nd\n";
+        assert_eq!(
+            merge3(base, "a\nB\nc\nd\n", "a\nb\nc\nD\ne\n"),
+            "a\nB\nc\nD\ne\n"
+        );
+        assert_eq!(merge3(base, base, "x\n"), "x\n");
+        assert_eq!(merge3(base, "a\nB\nc\nd\n", "a\nB\nc\nd\n"), "a\nB\nc\nd\n");
+    }
+
+    #[test]
+    fn overlapping_edits_keep_both_between_markers() {
+        assert_eq!(
+            merge3("a\nb\nc\n", "a\nmine\nc\n", "a\ntheirs\nc\n"),
+            "a\n<<<<<<< mine\nmine\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+        // Without a final newline, markers still start their own line.
+        assert_eq!(
+            merge3("a", "b", "c"),
+            "<<<<<<< mine\nb\n=======\nc\n>>>>>>> theirs\n"
+        );
+    }
+
+    #[test]
+    fn lines_deleted_on_one_side_stay_deleted() {
+        let base = "a\nb\nc\n";
+        assert_eq!(merge3(base, "a\nc\n", base), "a\nc\n");
+        assert_eq!(merge3(base, base, "a\nb\n"), "a\nb\n");
+        assert_eq!(merge3(base, "a\nc\n", "a\nb\nc\nd\n"), "a\nc\nd\n");
+        // Deleted by one side, edited by the other.
+        assert_eq!(
+            merge3(base, "a\nc\n", "a\nB\nc\n"),
+            "a\n<<<<<<< mine\n=======\nB\n>>>>>>> theirs\nc\n"
+        );
+    }
+}
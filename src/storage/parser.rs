@@ -0,0 +1,1164 @@
+use std::fmt::{self, Display, Write};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chumsky::{
+    prelude::*,
+    span::Spanned,
+    text::{Char, digits, whitespace},
+};
+use eyre::eyre;
+use serde::{Serialize, Serializer};
+
+use crate::storage::{BoxState, Date, format_date};
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub key: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A field without special meaning, kept as it was parsed.
+    Unknown(Node),
+    Date(Date),
+    BoxList(Vec<BoxState>),
+    TagList(Vec<String>),
+    Rename(String),
+}
+
+/// A value in the subset of YAML accepted in frontmatter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Null,
+    Scalar(Scalar),
+    Seq {
+        items: Vec<Node>,
+        flow: bool,
+    },
+    Map {
+        entries: Vec<(String, Node)>,
+        flow: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scalar {
+    pub text: String,
+    pub style: ScalarStyle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarStyle {
+    Plain,
+    SingleQuoted,
+    DoubleQuoted,
+    /// `|` block scalar.
+    Literal,
+    /// `>` block scalar.
+    Folded,
+}
+
+impl Node {
+    pub fn plain(text: String) -> Self {
+        Node::Scalar(Scalar {
+            text,
+            style: ScalarStyle::Plain,
+        })
+    }
+    pub fn as_scalar(&self) -> Option<&str> {
+        match self {
+            Node::Scalar(s) => Some(&s.text),
+            _ => None,
+        }
+    }
+}
+
+/// Scalars are written as strings, since their YAML types aren't resolved.
+impl Serialize for Node {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Node::Null => serializer.serialize_unit(),
+            Node::Scalar(scalar) => serializer.serialize_str(&scalar.text),
+            Node::Seq { items, .. } => serializer.collect_seq(items),
+            Node::Map { entries, .. } => {
+                serializer.collect_map(entries.iter().map(|(key, node)| (key, node)))
+            }
+        }
+    }
+}
+
+impl Value {
+    /// The generic form of this value, as it is written out.
+    pub fn to_node(&self) -> Node {
+        fn plain_seq(items: impl Iterator<Item = String>) -> Node {
+            Node::Seq {
+                items: items.map(Node::plain).collect(),
+                flow: false,
+            }
+        }
+        match self {
+            Value::Unknown(node) => node.clone(),
+            Value::Date(date) => Node::plain(format_date(date)),
+            Value::BoxList(box_states) => plain_seq(box_states.iter().map(|b| b.to_string())),
+            Value::TagList(items) => plain_seq(items.iter().cloned()),
+            Value::Rename(t) => Node::plain(t.clone()),
+        }
+    }
+}
+
+impl Field {
+    pub fn new(key: &str, value: Value) -> Self {
+        Self {
+            key: key.to_string(),
+            value,
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_key(f, &self.key, false)?;
+        f.write_char(':')?;
+        write_value(f, &self.value.to_node(), 2)
+    }
+}
+
+/// Writes everything after a `key:` or `-` indicator, including the final
+/// newline. Nested block content is indented to `indent`.
+fn write_value(f: &mut impl Write, node: &Node, indent: usize) -> fmt::Result {
+    match node {
+        Node::Null => writeln!(f),
+        // Block sequences cannot be empty, so write them out as null like
+        // they are read.
+        Node::Seq { items, flow: false } if items.is_empty() => writeln!(f),
+        Node::Seq { flow: false, .. } | Node::Map { flow: false, .. } if !is_empty(node) => {
+            writeln!(f)?;
+            write_block(f, node, 0, indent)
+        }
+        Node::Scalar(s)
+            if matches!(s.style, ScalarStyle::Literal | ScalarStyle::Folded)
+                || s.text.contains('\n') =>
+        {
+            write_block_scalar(f, s, indent)
+        }
+        _ => {
+            write!(f, " ")?;
+            write_flow(f, node, false)?;
+            writeln!(f)
+        }
+    }
+}
+
+fn is_empty(node: &Node) -> bool {
+    match node {
+        Node::Seq { items, .. } => items.is_empty(),
+        Node::Map { entries, .. } => entries.is_empty(),
+        _ => false,
+    }
+}
+
+/// Writes the entries of a non-empty block collection, starting with the
+/// cursor at column `column`. This lets nested collections start on the same
+/// line as their `-`.
+fn write_block(f: &mut impl Write, node: &Node, column: usize, indent: usize) -> fmt::Result {
+    let pad = |i: usize| if i == 0 { indent - column } else { indent };
+    match node {
+        Node::Seq { items, .. } => {
+            for (i, item) in items.iter().enumerate() {
+                write!(f, "{:1$}-", "", pad(i))?;
+                match item {
+                    Node::Seq { flow: false, .. } | Node::Map { flow: false, .. }
+                        if !is_empty(item) =>
+                    {
+                        write!(f, " ")?;
+                        write_block(f, item, indent + 2, indent + 2)?;
+                    }
+                    _ => write_value(f, item, indent + 2)?,
+                }
+            }
+        }
+        Node::Map { entries, .. } => {
+            for (i, (key, value)) in entries.iter().enumerate() {
+                write!(f, "{:1$}", "", pad(i))?;
+                write_key(f, key, false)?;
+                f.write_char(':')?;
+                write_value(f, value, indent + 2)?;
+            }
+        }
+        _ => unreachable!("only called on block collections"),
+    }
+    Ok(())
+}
+
+fn write_block_scalar(f: &mut impl Write, s: &Scalar, indent: usize) -> fmt::Result {
+    let body = s.text.trim_end_matches('\n');
+    let trailing = s.text.len() - body.len();
+    let indicator = match s.style {
+        ScalarStyle::Folded => '>',
+        _ => '|',
+    };
+    let chomp = match trailing {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    // Leading spaces would otherwise be taken as indentation.
+    let explicit_indent = if body.starts_with(' ') { "2" } else { "" };
+    writeln!(f, " {indicator}{explicit_indent}{chomp}")?;
+
+    let mut lines = vec![];
+    for (i, segment) in body.split('\n').enumerate() {
+        // Every line break in folded text is spelled as an empty line.
+        if i > 0 && indicator == '>' {
+            lines.push("");
+        }
+        if !(segment.is_empty() && indicator == '>' && i > 0) {
+            lines.push(segment);
+        }
+    }
+    for line in lines {
+        if line.is_empty() {
+            writeln!(f)?;
+        } else {
+            writeln!(f, "{:indent$}{line}", "")?;
+        }
+    }
+    for _ in 1..trailing {
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+fn write_flow(f: &mut impl Write, node: &Node, in_flow: bool) -> fmt::Result {
+    match node {
+        Node::Null => write!(f, "null"),
+        Node::Scalar(s) => write_inline_scalar(f, s, in_flow),
+        Node::Seq { items, .. } => {
+            write!(f, "[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_flow(f, item, true)?;
+            }
+            write!(f, "]")
+        }
+        Node::Map { entries, .. } => {
+            write!(f, "{{")?;
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_key(f, key, true)?;
+                write!(f, ": ")?;
+                write_flow(f, value, true)?;
+            }
+            write!(f, "}}")
+        }
+    }
+}
+
+fn write_inline_scalar(f: &mut impl Write, s: &Scalar, in_flow: bool) -> fmt::Result {
+    match s.style {
+        ScalarStyle::Plain if is_plain_safe(&s.text, in_flow) => f.write_str(&s.text),
+        ScalarStyle::SingleQuoted if !s.text.contains('\n') => {
+            write!(f, "'{}'", s.text.replace('\'', "''"))
+        }
+        _ => write_double_quoted(f, &s.text),
+    }
+}
+
+/// Quoted unless it reads back as the same plain key.
+fn write_key(f: &mut impl Write, key: &str, in_flow: bool) -> fmt::Result {
+    if is_plain_safe(key, in_flow) {
+        f.write_str(key)
+    } else {
+        write_double_quoted(f, key)
+    }
+}
+
+/// Writes `text` as read back by `double_quoted`.
+pub fn write_double_quoted(f: &mut impl Write, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Whether `text` reads back as the same plain scalar.
+fn is_plain_safe(text: &str, in_flow: bool) -> bool {
+    const INDICATORS: &[char] = &[
+        '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+    ];
+    !text.is_empty()
+        && text.trim() == text
+        && !text.starts_with(INDICATORS)
+        && text != "-"
+        && !text.starts_with("- ")
+        && !text.contains(": ")
+        && !text.ends_with(':')
+        && !text.contains(" #")
+        && !text.contains(|c: char| c.is_newline())
+        && (!in_flow || !text.contains([',', '[', ']', '{', '}']))
+}
+
+pub struct Frontmatter {
+    line_offset: usize,
+    text: String,
+    pub parsed_fields: Vec<Spanned<(String, Node)>>,
+}
+impl Frontmatter {
+    pub fn get_location(&self, byte: usize) -> (usize, usize) {
+        let s = self.text.split_at(byte).0;
+        let out = (
+            self.line_offset + s.lines().count(),
+            1 + s.lines().last().map(|l| l.chars().count()).unwrap_or(0),
+        );
+        // instead of reporting the last character of a line, report the next line.
+        if s.ends_with(|s: char| s.is_newline()) && self.text.lines().count() > out.0 {
+            return (out.0 + 1, 0);
+        }
+        out
+    }
+}
+
+pub fn parse_fields(frontmatter: &str, line_offset: usize) -> Result<Frontmatter, eyre::Report> {
+    let mut out = Frontmatter {
+        line_offset,
+        text: frontmatter.to_string(),
+        parsed_fields: vec![],
+    };
+
+    out.parsed_fields = BlockParser::new(frontmatter).fields().map_err(|e| {
+        let (line, col) = out.get_location(e.at);
+        eyre!("parsing fields. {} at {line}:{col}", e.reason)
+    })?;
+
+    Ok(out)
+}
+
+struct BlockError {
+    at: usize,
+    reason: String,
+}
+
+impl BlockError {
+    fn new(at: usize, reason: impl Into<String>) -> Self {
+        Self {
+            at,
+            reason: reason.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Line<'src> {
+    /// Byte offset of `text`.
+    start: usize,
+    /// Byte offset past the end of the line, including the newline.
+    end: usize,
+    indent: usize,
+    /// Line content after the indentation, without the newline.
+    text: &'src str,
+}
+
+impl Line<'_> {
+    /// Byte offset of `sub`, which must be a slice of `text`.
+    fn offset_of(&self, sub: &str) -> usize {
+        self.start + (sub.as_ptr() as usize - self.text.as_ptr() as usize)
+    }
+    fn is_blank(&self) -> bool {
+        let t = self.text.trim();
+        t.is_empty() || t.starts_with('#')
+    }
+}
+
+/// Parses the indentation structure of the frontmatter. Inline values (flow
+/// collections and quoted strings) are handed off to chumsky parsers.
+struct BlockParser<'src> {
+    lines: Vec<Line<'src>>,
+    pos: usize,
+    /// End of the last line that held content.
+    last_end: usize,
+}
+
+type BlockResult<T> = Result<T, BlockError>;
+
+impl<'src> BlockParser<'src> {
+    fn new(text: &'src str) -> Self {
+        let mut lines = vec![];
+        let mut start = 0;
+        for raw in text.split_inclusive('\n') {
+            let content = raw.trim_end_matches(['\n', '\r']);
+            let trimmed = content.trim_start_matches(' ');
+            let indent = content.len() - trimmed.len();
+            lines.push(Line {
+                start: start + indent,
+                end: start + raw.len(),
+                indent,
+                text: trimmed.trim_end(),
+            });
+            start += raw.len();
+        }
+        Self {
+            lines,
+            pos: 0,
+            last_end: 0,
+        }
+    }
+
+    fn fields(mut self) -> BlockResult<Vec<Spanned<(String, Node)>>> {
+        let mut fields = vec![];
+        while let Some(line) = self.peek() {
+            if line.indent != 0 {
+                return Err(BlockError::new(line.start, "unexpected indentation"));
+            }
+            let Some((key, rest)) = split_key(line.text) else {
+                return Err(BlockError::new(line.start, "expected 'key: value'"));
+            };
+            let key = unquote_key(key, line.start)?;
+            self.consume(line);
+            let value = self.value(0, line.offset_of(rest), rest, true)?;
+            fields.push(Spanned {
+                inner: (key, value),
+                span: SimpleSpan::from(line.start..self.last_end),
+            });
+        }
+        Ok(fields)
+    }
+
+    /// Next line with content, skipping blank lines and comments.
+    fn peek(&mut self) -> Option<Line<'src>> {
+        while self.lines.get(self.pos).is_some_and(|l| l.is_blank()) {
+            self.pos += 1;
+        }
+        self.lines.get(self.pos).copied()
+    }
+
+    fn consume(&mut self, line: Line) {
+        self.pos += 1;
+        self.last_end = line.end;
+    }
+
+    /// Parses the value after a `key:` or `-` whose line is indented by
+    /// `parent_indent`. `rest` is the remainder of that line.
+    fn value(
+        &mut self,
+        parent_indent: usize,
+        rest_start: usize,
+        rest: &'src str,
+        allow_compact_seq: bool,
+    ) -> BlockResult<Node> {
+        let text = strip_comment(rest).trim();
+        let text_start = rest_start + (rest.len() - rest.trim_start().len());
+        if text.is_empty() {
+            return match self.peek() {
+                Some(l) if l.indent > parent_indent => self.node_at_line(parent_indent),
+                // `key:` followed by `- item` at the same indentation.
+                Some(l)
+                    if l.indent == parent_indent && allow_compact_seq && is_seq_item(l.text) =>
+                {
+                    self.block_seq(parent_indent)
+                }
+                _ => Ok(Node::Null),
+            };
+        }
+        if text.starts_with(['|', '>']) {
+            return self.block_scalar(parent_indent, text_start, text);
+        }
+        self.inline_node(parent_indent, text_start, text)
+    }
+
+    /// Parses the structure starting at the next line, which is indented past
+    /// `parent_indent`.
+    fn node_at_line(&mut self, parent_indent: usize) -> BlockResult<Node> {
+        let line = self.peek().expect("checked by caller");
+        if is_seq_item(line.text) {
+            self.block_seq(line.indent)
+        } else if split_key(line.text).is_some() {
+            self.block_map(line.indent)
+        } else {
+            self.consume(line);
+            self.value(parent_indent, line.start, line.text, false)
+        }
+    }
+
+    fn block_seq(&mut self, indent: usize) -> BlockResult<Node> {
+        let mut items = vec![];
+        while let Some(line) = self.peek() {
+            if line.indent < indent || (line.indent == indent && !is_seq_item(line.text)) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(BlockError::new(line.start, "unexpected indentation"));
+            }
+            let after = &line.text[1..];
+            let rest = after.trim_start();
+            if rest.is_empty() || rest.starts_with('#') {
+                self.consume(line);
+                items.push(self.value(indent, line.start + 1, after, false)?);
+            } else {
+                // Treat the rest of the line as a line of its own, indented to
+                // where its content starts, so nested structure parses the
+                // same way as on separate lines.
+                let offset = 1 + after.len() - rest.len();
+                self.lines[self.pos] = Line {
+                    start: line.start + offset,
+                    end: line.end,
+                    indent: indent + offset,
+                    text: rest,
+                };
+                items.push(self.node_at_line(indent)?);
+            }
+        }
+        Ok(Node::Seq { items, flow: false })
+    }
+
+    fn block_map(&mut self, indent: usize) -> BlockResult<Node> {
+        let mut entries = vec![];
+        while let Some(line) = self.peek() {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(BlockError::new(line.start, "unexpected indentation"));
+            }
+            let Some((key, rest)) = split_key(line.text) else {
+                if is_seq_item(line.text) {
+                    // A compact sequence belonging to an enclosing key.
+                    break;
+                }
+                return Err(BlockError::new(line.start, "expected 'key: value'"));
+            };
+            let key = unquote_key(key, line.start)?;
+            self.consume(line);
+            let value = self.value(indent, line.offset_of(rest), rest, true)?;
+            entries.push((key, value));
+        }
+        Ok(Node::Map {
+            entries,
+            flow: false,
+        })
+    }
+
+    fn block_scalar(
+        &mut self,
+        parent_indent: usize,
+        header_start: usize,
+        header: &str,
+    ) -> BlockResult<Node> {
+        let style = if header.starts_with('|') {
+            ScalarStyle::Literal
+        } else {
+            ScalarStyle::Folded
+        };
+        let mut chomp = None;
+        let mut explicit_indent = None;
+        for c in header[1..].chars() {
+            match c {
+                '-' | '+' if chomp.is_none() => chomp = Some(c),
+                '1'..='9' if explicit_indent.is_none() => {
+                    explicit_indent = c.to_digit(10).map(|d| parent_indent + d as usize)
+                }
+                _ => {
+                    return Err(BlockError::new(
+                        header_start,
+                        format!("invalid block scalar header '{header}'"),
+                    ));
+                }
+            }
+        }
+
+        // Comments are content inside block scalars, so only skip empty lines.
+        let mut content_indent = explicit_indent;
+        let mut lines = vec![];
+        let mut trailing_empty = 0;
+        while let Some(line) = self.lines.get(self.pos).copied() {
+            if line.text.is_empty() {
+                self.pos += 1;
+                trailing_empty += 1;
+                lines.push(String::new());
+                continue;
+            }
+            let indent = *content_indent.get_or_insert(line.indent);
+            if line.indent < indent || line.indent <= parent_indent {
+                break;
+            }
+            self.consume(line);
+            trailing_empty = 0;
+            lines.push(" ".repeat(line.indent - indent) + line.text);
+        }
+        lines.truncate(lines.len() - trailing_empty);
+
+        let mut text = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            if i == 0 {
+                text += line;
+                continue;
+            }
+            let prev = &lines[i - 1];
+            let folds = style == ScalarStyle::Folded
+                && !prev.is_empty()
+                && !prev.starts_with(' ')
+                && !line.is_empty()
+                && !line.starts_with(' ');
+            if folds {
+                text.push(' ');
+            } else if style == ScalarStyle::Literal || !prev.is_empty() || line.is_empty() {
+                text.push('\n');
+            }
+            text += line;
+        }
+        match chomp {
+            Some('-') => {}
+            Some('+') => text += &"\n".repeat(trailing_empty + usize::from(!lines.is_empty())),
+            _ if !lines.is_empty() => text.push('\n'),
+            _ => {}
+        }
+        Ok(Node::Scalar(Scalar { text, style }))
+    }
+
+    /// Parses a plain, quoted, or flow value that starts on an already
+    /// consumed line and may continue onto more indented lines.
+    fn inline_node(&mut self, parent_indent: usize, start: usize, text: &str) -> BlockResult<Node> {
+        let mut joined = text.to_string();
+        while let Some(line) = self.lines.get(self.pos).copied() {
+            let unfinished = match joined.chars().next() {
+                Some('[' | '{' | '"' | '\'') => !is_closed(&joined),
+                _ => true,
+            };
+            if !unfinished || line.is_blank() || line.indent <= parent_indent {
+                break;
+            }
+            self.consume(line);
+            joined.push(' ');
+            joined += strip_comment(line.text).trim();
+        }
+
+        if !joined.starts_with(['[', '{', '"', '\'']) {
+            return Ok(Node::plain(joined));
+        }
+        flow_node()
+            .padded()
+            .then_ignore(end())
+            .parse(&joined)
+            .into_result()
+            .map_err(|e| match e.first() {
+                Some(e) => BlockError::new(
+                    start + e.span().start.min(text.len()),
+                    e.reason().to_string(),
+                ),
+                None => BlockError::new(start, "missing error"),
+            })
+    }
+}
+
+/// Splits `key: value` lines. Returns `None` for anything else.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with(['-', '[', '{', '#', '|', '>']) {
+        return None;
+    }
+    let key_end = if let Some(quote) = text.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        let key_end = closing_quote(text, quote)? + 1;
+        // Only spaces may come between a quoted key and its colon.
+        if !text[key_end..].trim_start().starts_with(':') {
+            return None;
+        }
+        key_end
+    } else {
+        0
+    };
+    let colon = key_end
+        + text[key_end..]
+            .char_indices()
+            .find(|(i, c)| {
+                *c == ':'
+                    && text[key_end + i + 1..]
+                        .chars()
+                        .next()
+                        .is_none_or(|c| c.is_whitespace())
+            })?
+            .0;
+    let key = text[..colon].trim_end();
+    if key.is_empty() || (key_end == 0 && key.contains(" #")) {
+        return None;
+    }
+    Some((key, &text[colon + 1..]))
+}
+
+/// Byte offset of the quote closing the one `text` starts with.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if quote == '"' && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            // `''` is an escaped quote in single quoted scalars.
+            if quote == '\'' && chars.peek().is_some_and(|(_, c)| *c == '\'') {
+                chars.next();
+            } else {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// `key` is a whole quoted scalar, as checked by `split_key`, or plain. It
+/// starts at byte `start`.
+fn unquote_key(key: &str, start: usize) -> BlockResult<String> {
+    let quoted = match key.chars().next() {
+        Some('"') => double_quoted().parse(key),
+        Some('\'') => single_quoted().parse(key),
+        _ => return Ok(key.to_string()),
+    };
+    quoted.into_result().map_err(|e| match e.first() {
+        Some(e) => BlockError::new(start + e.span().start, e.reason().to_string()),
+        None => BlockError::new(start, "missing error"),
+    })
+}
+
+fn is_seq_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Removes a trailing `# comment`, ignoring `#` inside quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let mut prev_whitespace = true;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some('"') if c == '\\' => {
+                chars.next();
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && prev_whitespace => return &text[..i],
+            None => {}
+        }
+        prev_whitespace = c.is_whitespace();
+    }
+    text
+}
+
+/// Whether the quotes and brackets opened in `text` are all closed.
+fn is_closed(text: &str) -> bool {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some('"') if c == '\\' => {
+                chars.next();
+            }
+            Some('\'') if c == '\'' && chars.peek() == Some(&'\'') => {
+                chars.next();
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            },
+        }
+    }
+    depth == 0 && quote.is_none()
+}
+
+fn flow_node<'src>() -> impl Parser<'src, &'src str, Node, extra::Err<Rich<'src, char>>> {
+    recursive(|node| {
+        let seq = node
+            .clone()
+            .padded()
+            .separated_by(just(','))
+            .allow_trailing()
+            .collect::<Vec<_>>()
+            .delimited_by(just('[').then(whitespace()), just(']'))
+            .map(|items| Node::Seq { items, flow: true });
+        let key = double_quoted().or(single_quoted()).or(none_of(",[]{}:\"'")
+            .repeated()
+            .at_least(1)
+            .to_slice()
+            .map(|k: &str| k.trim().to_string()));
+        let map = key
+            .padded()
+            .then_ignore(just(':'))
+            .then(
+                node.clone()
+                    .or_not()
+                    .padded()
+                    .map(|n| n.unwrap_or(Node::Null)),
+            )
+            .separated_by(just(','))
+            .allow_trailing()
+            .collect::<Vec<_>>()
+            .delimited_by(just('{').then(whitespace()), just('}'))
+            .map(|entries| Node::Map {
+                entries,
+                flow: true,
+            });
+        let plain = none_of(",[]{}\"'")
+            .then(none_of(",[]{}").repeated())
+            .to_slice()
+            .map(|s: &str| Node::plain(s.trim().to_string()));
+        choice((
+            seq,
+            map,
+            double_quoted().map(|text| {
+                Node::Scalar(Scalar {
+                    text,
+                    style: ScalarStyle::DoubleQuoted,
+                })
+            }),
+            single_quoted().map(|text| {
+                Node::Scalar(Scalar {
+                    text,
+                    style: ScalarStyle::SingleQuoted,
+                })
+            }),
+            plain,
+        ))
+    })
+}
+
+fn double_quoted<'src>()
+-> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>> + Clone {
+    let escape = just('\\').ignore_then(choice((
+        just('n').to('\n'),
+        just('t').to('\t'),
+        just('r').to('\r'),
+        just('0').to('\0'),
+        just('"'),
+        just('\\'),
+        just('/'),
+        just(' '),
+    )));
+    escape
+        .or(none_of("\\\""))
+        .repeated()
+        .collect::<String>()
+        .delimited_by(just('"'), just('"'))
+}
+
+fn single_quoted<'src>()
+-> impl Parser<'src, &'src str, String, extra::Err<Rich<'src, char>>> + Clone {
+    just("''")
+        .to('\'')
+        .or(none_of("'"))
+        .repeated()
+        .collect::<String>()
+        .delimited_by(just('\''), just('\''))
+}
+
+/// Accepts `YYYY-MM-DD`, optionally followed by `THH:MM` or `THH:MM:SS`
+/// (with `T` or a space), as written by Obsidian's date properties.
+fn date<'src>() -> impl Parser<'src, &'src str, NaiveDateTime, extra::Err<Rich<'src, char>>> {
+    let day = digits(10)
+        .exactly(4)
+        .then(just("-"))
+        .then(digits(10).exactly(2))
+        .then(just("-"))
+        .then(digits(10).exactly(2))
+        .to_slice();
+    let time = digits(10)
+        .exactly(2)
+        .then(just(":"))
+        .then(digits(10).exactly(2))
+        .then(just(":").then(digits(10).exactly(2)).or_not())
+        .to_slice();
+    day.then(one_of("T ").ignore_then(time).or_not()).try_map(
+        |(day, time): (&str, Option<&str>), span| {
+            let day =
+                NaiveDate::parse_from_str(day, "%Y-%m-%d").map_err(|e| Rich::custom(span, e))?;
+            let time = match time {
+                None => NaiveTime::MIN,
+                Some(t) => NaiveTime::parse_from_str(t, "%H:%M:%S")
+                    .or_else(|_| NaiveTime::parse_from_str(t, "%H:%M"))
+                    .map_err(|e| Rich::custom(span, e))?,
+            };
+            Ok(day.and_time(time))
+        },
+    )
+}
+pub fn date_field<'src>()
+-> impl Parser<'src, &'src str, NaiveDateTime, extra::Err<Rich<'src, char>>> {
+    date().then_ignore(end())
+}
+/// `Started(start)`, `Checked(start, end)`, `Voided(start, end[, "reason"])`,
+/// or `Empty`. `Started` and `Checked(end)` from before start times were
+/// recorded are accepted too.
+pub fn box_field<'src>() -> impl Parser<'src, &'src str, BoxState, extra::Err<Rich<'src, char>>> {
+    let arguments = || {
+        date()
+            .separated_by(just(",").padded())
+            .at_least(1)
+            .at_most(2)
+            .collect::<Vec<_>>()
+    };
+    let dates = |dates: Vec<Date>| match dates[..] {
+        [start, end] => (Some(start), end),
+        _ => (None, dates[0]),
+    };
+    choice((
+        just("Started")
+            .ignore_then(date().delimited_by(just("("), just(")")).or_not())
+            .map(BoxState::Started),
+        just("Empty").to(BoxState::Empty),
+        just("Checked")
+            .ignore_then(arguments().delimited_by(just("("), just(")")))
+            .map(dates)
+            .map(|(start, end)| BoxState::Checked { start, end }),
+        just("Voided")
+            .ignore_then(
+                arguments()
+                    .then(just(",").padded().ignore_then(double_quoted()).or_not())
+                    .delimited_by(just("("), just(")")),
+            )
+            .map(move |(d, reason)| {
+                let (start, end) = dates(d);
+                BoxState::Voided { start, end, reason }
+            }),
+    ))
+    .then_ignore(end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(text: &str) -> Vec<(String, Node)> {
+        parse_fields(text, 0)
+            .unwrap()
+            .parsed_fields
+            .into_iter()
+            .map(|f| f.inner)
+            .collect()
+    }
+
+    fn plain(text: &str) -> Node {
+        Node::plain(text.to_string())
+    }
+
+    fn scalar(text: &str, style: ScalarStyle) -> Node {
+        Node::Scalar(Scalar {
+            text: text.to_string(),
+            style,
+        })
+    }
+
+    fn written(fields: &[(String, Node)]) -> String {
+        fields
+            .iter()
+            .map(|(key, node)| Field::new(key, Value::Unknown(node.clone())).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn block_collections() {
+        let parsed = fields("tags:\n  - a\n  - b\nowner:\n  name: bob\n  teams:\n  - x\n");
+        assert_eq!(
+            parsed,
+            [
+                (
+                    "tags".to_string(),
+                    Node::Seq {
+                        items: vec![plain("a"), plain("b")],
+                        flow: false,
+                    }
+                ),
+                (
+                    "owner".to_string(),
+                    Node::Map {
+                        entries: vec![
+                            ("name".to_string(), plain("bob")),
+                            (
+                                "teams".to_string(),
+                                Node::Seq {
+                                    items: vec![plain("x")],
+                                    flow: false,
+                                }
+                            ),
+                        ],
+                        flow: false,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_sequence_at_key_indentation() {
+        let parsed = fields("tags:\n- a\n- b\n");
+        assert_eq!(
+            parsed[0].1,
+            Node::Seq {
+                items: vec![plain("a"), plain("b")],
+                flow: false,
+            }
+        );
+    }
+
+    #[test]
+    fn flow_collections() {
+        let parsed = fields("tags: [a, \"b, c\", {k: v}]\n");
+        assert_eq!(
+            parsed[0].1,
+            Node::Seq {
+                items: vec![
+                    plain("a"),
+                    scalar("b, c", ScalarStyle::DoubleQuoted),
+                    Node::Map {
+                        entries: vec![("k".to_string(), plain("v"))],
+                        flow: true,
+                    },
+                ],
+                flow: true,
+            }
+        );
+    }
+
+    #[test]
+    fn flow_collection_over_lines() {
+        let parsed = fields("tags: [a,\n  b]\n");
+        assert_eq!(
+            parsed[0].1,
+            Node::Seq {
+                items: vec![plain("a"), plain("b")],
+                flow: true,
+            }
+        );
+    }
+
+    #[test]
+    fn quoted_scalars_and_keys() {
+        let parsed = fields("'it''s': 'a ''b'''\n\"a\\\"b\": \"x\\ny\"\n\"c\" : d\n");
+        assert_eq!(
+            parsed,
+            [
+                (
+                    "it's".to_string(),
+                    scalar("a 'b'", ScalarStyle::SingleQuoted)
+                ),
+                (
+                    "a\"b".to_string(),
+                    scalar("x\ny", ScalarStyle::DoubleQuoted)
+                ),
+                ("c".to_string(), plain("d")),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_scalars() {
+        let parsed = fields(
+            "a: |\n  one\n  two\nb: >\n  one\n  two\n\n  three\nc: |-\n  keep\nd: |+\n  x\n\n",
+        );
+        assert_eq!(
+            parsed,
+            [
+                ("a".to_string(), scalar("one\ntwo\n", ScalarStyle::Literal)),
+                (
+                    "b".to_string(),
+                    scalar("one two\nthree\n", ScalarStyle::Folded)
+                ),
+                ("c".to_string(), scalar("keep", ScalarStyle::Literal)),
+                ("d".to_string(), scalar("x\n\n", ScalarStyle::Literal)),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let parsed = fields("# about\na: b # note\nc: \"# kept\"\nd: e#f\n");
+        assert_eq!(
+            parsed,
+            [
+                ("a".to_string(), plain("b")),
+                ("c".to_string(), scalar("# kept", ScalarStyle::DoubleQuoted)),
+                ("d".to_string(), plain("e#f")),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_values_are_null() {
+        let parsed = fields("a:\nb: # note\n");
+        assert_eq!(
+            parsed,
+            [("a".to_string(), Node::Null), ("b".to_string(), Node::Null)]
+        );
+    }
+
+    #[test]
+    fn written_fields_read_back() {
+        for text in [
+            "tags:\n  - a\n  - b\n",
+            "tags: [a, \"b, c\", {k: v}]\n",
+            "owner:\n  name: bob\n  teams: [x, y]\n",
+            "'it''s': 'a ''b'''\n",
+            "\"a\\\"b\": x\n",
+            "\"a: b\": c\n",
+            "a: |\n  one\n   two\n",
+            "b: >-\n  one\n  two\n",
+            "c: \"  padded\"\n",
+            "d:\n",
+            "e:\n  - - x\n    - y\n  - k: v\n",
+        ] {
+            let parsed = fields(text);
+            assert_eq!(fields(&written(&parsed)), parsed, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for text in [
+            "''é: x\n",
+            "\"\t\"\té: \n",
+            "''{é: \n",
+            "\"a\" b: c\n",
+            "\"a\\q\": b\n",
+            "  indented: x\n",
+            "- item\n",
+            "no colon\n",
+            "a: [b\n",
+            "a: {b: c\n",
+            "a: \"b\n",
+            "a: |x\n  y\n",
+        ] {
+            assert!(parse_fields(text, 0).is_err(), "{text:?}");
+        }
+    }
+
+    /// Every short combination of characters the parser treats specially
+    /// returns, rather than panicking.
+    #[test]
+    fn short_inputs_do_not_panic() {
+        const ALPHABET: [&str; 12] = [
+            "'", "\"", "\\", ":", " ", "é", "{", "[", "-", "\n", "#", "|",
+        ];
+        let mut texts = vec![String::new()];
+        for _ in 0..4 {
+            texts = texts
+                .iter()
+                .flat_map(|t| ALPHABET.iter().map(move |c| t.clone() + c))
+                .collect();
+            for text in &texts {
+                let _ = parse_fields(text, 0);
+                let _ = parse_fields(&format!("{text}: x\n"), 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{Receiver, channel},
+    time::{Duration, Instant},
+};
+
+use eyre::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long a file must go without events before it is reported, so that a
+/// file is not read while another program is still writing it.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Watches the data directory for task files changed by other programs.
+pub struct DirWatcher {
+    dir: PathBuf,
+    // Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl DirWatcher {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let (tx, events) = channel();
+        let mut watcher = notify::recommended_watcher(tx).wrap_err("creating file watcher")?;
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .wrap_err_with(|| format!("watching '{}'", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            _watcher: watcher,
+            events,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Task files that changed, were added, or were removed, and have since
+    /// settled.
+    pub fn changed_paths(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(e) => e,
+                Err(e) => {
+                    log::warn!("file watcher error {e}");
+                    continue;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if is_task_file(&self.dir, &path) {
+                    self.pending.insert(path, now);
+                }
+            }
+        }
+        let settled: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, last_event)| now.duration_since(**last_event) >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &settled {
+            self.pending.remove(path);
+        }
+        settled
+    }
+}
+
+/// Markdown files in `dir`, outside of hidden directories.
+fn is_task_file(dir: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(dir) else {
+        return false;
+    };
+    path.extension().is_some_and(|e| e == "md")
+        && relative.components().all(|c| match c {
+            Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+            _ => false,
+        })
+}
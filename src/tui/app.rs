@@ -1,9 +1,10 @@
 use std::{
     cell::{RefCell, RefMut},
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     rc::Rc,
 };
 
+use chrono::Local;
 use crossterm::event::KeyCode;
 use ratatui::{
     crossterm::event::{KeyEvent, KeyModifiers},
@@ -14,15 +15,21 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     FocusState, PopupEnum,
-    filter::FilteredData,
+    filter::{FilteredData, Query, TaskID},
+    storage::{BoxState, Conflict, Date},
     tui::{
-        filter::{FilterTui, FilterWidget},
+        filter::{CompletionWidget, FilterTui, FilterWidget},
+        history::History,
         popup::{
             self, PopupTui, PopupWidget,
-            dialog::{ErrorDialog, SaveDialog},
+            dialog::{
+                ConflictDialog, DeleteDialog, ErrorDialog, ReportDialog, SaveDialog, TrashDialog,
+                VoidDialog,
+            },
         },
         table::{TableTui, TableWidget},
         task::{TaskFocus, TaskTui, TaskWidget},
+        timer::{TimerTui, TimerWidget},
     },
 };
 
@@ -32,8 +39,11 @@ pub struct AppTui<'a> {
     table: TableTui,
     task: TaskTui,
     popup: PopupTui,
+    timer: TimerTui,
     mode: Mode,
     keybinds: HashMap<Mode, HashMap<KeyCode, KeyAction>>,
+    /// Popups waiting for the one shown to close.
+    queued: VecDeque<PopupEnum<'a>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -52,16 +62,22 @@ pub enum Mode {
     Key(KeyCode),
 }
 
-impl AppTui<'_> {
-    pub fn new(keybinds: HashMap<Mode, HashMap<KeyCode, KeyAction>>) -> Self {
+impl<'a> AppTui<'a> {
+    pub fn new(
+        keybinds: HashMap<Mode, HashMap<KeyCode, KeyAction>>,
+        timer: TimerTui,
+        history: History,
+    ) -> Self {
         Self {
-            filter: FilterTui::new(),
+            filter: FilterTui::new(history),
             focus: FocusState::List,
             table: TableTui::new(),
             task: TaskTui::new(),
             popup: PopupTui::new(),
+            timer,
             mode: Mode::Normal,
             keybinds,
+            queued: VecDeque::new(),
         }
     }
 
@@ -76,10 +92,94 @@ impl AppTui<'_> {
         };
     }
 
+    /// Updates state that depends on tasks after they were reloaded from disk.
+    pub fn reloaded(&mut self, data: &FilteredData) {
+        self.task.reloaded();
+        self.table.clamp(data.len());
+    }
+
+    /// Resumes the timer for a box started before the app was opened.
+    pub fn resume_timer(&mut self, id: TaskID, start: Date) {
+        self.timer.start(id, start);
+    }
+
+    /// Advances the timer, checking the running box if it is configured to.
+    pub fn tick(&mut self, data: &mut FilteredData) {
+        let now = Local::now().naive_local();
+        self.keeping_selection(data, |app, data| app.timer.tick(data, now));
+        if data.refilter_if_new_day(now) {
+            self.table.clamp(data.len());
+        }
+    }
+
+    /// Selects the task selected before `change` again, as changing a task
+    /// can sort it elsewhere. Unless `change` moved the selection itself.
+    fn keeping_selection<T>(
+        &mut self,
+        data: &mut FilteredData,
+        change: impl FnOnce(&mut Self, &mut FilteredData) -> T,
+    ) -> T {
+        let before = self.table.selected().filter(|i| *i < data.len());
+        let selected = before.map(|i| data.get_id(i));
+        let out = change(self, data);
+        if self.table.selected() == before
+            && let Some(i) = selected.and_then(|id| data.visible_index(id))
+        {
+            self.table.set_selected(i);
+        }
+        out
+    }
+
+    /// Keeps the selected task selected if it is still shown.
+    fn set_query(&mut self, data: &mut FilteredData, query: Query) {
+        let selected = self.table.selected().map(|i| data.get_id(i));
+        data.set_query(query);
+        match selected.and_then(|id| data.visible_index(id)) {
+            Some(i) => self.table.set_selected(i),
+            None => self.table.clamp(data.len()),
+        }
+    }
+
+    pub fn queue_conflicts(&mut self, conflicts: Vec<Conflict>) {
+        for conflict in conflicts {
+            self.queue_popup(PopupEnum::Conflict(ConflictDialog::new(conflict)));
+        }
+    }
+
+    pub fn queue_popup(&mut self, popup: PopupEnum<'a>) {
+        self.queued.push_back(popup);
+        self.show_next_popup();
+    }
+
+    /// Shows the next queued popup, unless another popup is already open.
+    fn show_next_popup(&mut self) {
+        if matches!(self.focus, FocusState::Popup { .. }) {
+            return;
+        }
+        if let Some(popup) = self.queued.pop_front() {
+            self.focus = FocusState::Popup {
+                popup,
+                last_focus: self.focus.clone().into(),
+            };
+        }
+    }
+
     pub fn handle_key_event(
         &mut self,
         data: &mut FilteredData,
         key_event: KeyEvent,
+    ) -> Option<Action> {
+        let action = self.keeping_selection(data, |app, data| {
+            app.handle_focused_key_event(data, key_event)
+        });
+        self.show_next_popup();
+        action
+    }
+
+    fn handle_focused_key_event(
+        &mut self,
+        data: &mut FilteredData,
+        key_event: KeyEvent,
     ) -> Option<Action> {
         if key_event.code == KeyCode::Char('c')
             && key_event.modifiers.contains(KeyModifiers::CONTROL)
@@ -95,6 +195,45 @@ impl AppTui<'_> {
                         last_focus: self.focus.clone().into(),
                     }
                 }
+                super::table::Action::Delete => {
+                    let id = data.get_id(self.table.selected()?);
+                    let task = data.get(id)?;
+                    self.focus = FocusState::Popup {
+                        popup: PopupEnum::Delete(DeleteDialog::new(id, task.title())),
+                        last_focus: self.focus.clone().into(),
+                    }
+                }
+                super::table::Action::Void => {
+                    let id = data.get_id(self.table.selected()?);
+                    let task = data.get(id)?;
+                    if task
+                        .boxes()
+                        .iter()
+                        .any(|b| matches!(b, BoxState::Started(_)))
+                    {
+                        self.focus = FocusState::Popup {
+                            popup: PopupEnum::Void(VoidDialog::new(id)),
+                            last_focus: self.focus.clone().into(),
+                        }
+                    }
+                }
+                super::table::Action::Started(id, start) => self.timer.start(id, start),
+                super::table::Action::Checked(end) => self.timer.start_break(end),
+                super::table::Action::Report => {
+                    self.focus = FocusState::Popup {
+                        popup: PopupEnum::Report(ReportDialog::new(data)),
+                        last_focus: self.focus.clone().into(),
+                    }
+                }
+                super::table::Action::Trash => match data.trash() {
+                    Ok(trashed) => {
+                        self.focus = FocusState::Popup {
+                            popup: PopupEnum::Trash(TrashDialog::new(trashed)),
+                            last_focus: self.focus.clone().into(),
+                        }
+                    }
+                    Err(e) => self.set_error_focus(e.wrap_err("Error listing trash")),
+                },
                 super::table::Action::Unhandled => match key_event.code {
                     KeyCode::Char(' ') => {
                         self.focus = FocusState::Popup {
@@ -102,8 +241,12 @@ impl AppTui<'_> {
                             last_focus: self.focus.clone().into(),
                         }
                     }
-                    KeyCode::Char('f') => self.focus = FocusState::Filter,
+                    KeyCode::Char('f') => {
+                        self.filter.start_editing(data.query());
+                        self.focus = FocusState::Filter
+                    }
                     KeyCode::Char('t') => self.focus = FocusState::Task(TaskFocus::tags_locked()),
+                    KeyCode::Char('r') => self.focus = FocusState::Task(TaskFocus::title_locked()),
                     KeyCode::Enter => self.focus = FocusState::Task(TaskFocus::context_locked()),
                     KeyCode::Right => self.focus = FocusState::Task(TaskFocus::context_unlocked()),
                     _ => {
@@ -114,9 +257,13 @@ impl AppTui<'_> {
                         {
                             match action {
                                 KeyAction::SetFilter(s) => {
-                                    self.filter.set_text(s.clone());
-                                    if let Err(e) = data.set_filter(s) {
-                                        log::error!("encountered err {e} while updating filter");
+                                    let s = s.clone();
+                                    self.filter.set_text(s);
+                                    match self.filter.query().cloned() {
+                                        Ok(query) => self.set_query(data, query),
+                                        Err(e) => {
+                                            log::error!("encountered err {e} while updating filter")
+                                        }
                                     }
                                 }
                             }
@@ -124,13 +271,17 @@ impl AppTui<'_> {
                     }
                 },
             },
-            FocusState::Filter => match self.filter.handle_key(key_event)? {
-                super::filter::Action::Exit => self.focus = FocusState::List,
-                super::filter::Action::Updated(f) => {
-                    if let Err(e) = data.set_filter(&f) {
-                        log::error!("encountered err {e} while updating filter");
-                    } else {
-                        self.focus = FocusState::List
+            FocusState::Filter => match self.filter.handle_key(key_event, data)? {
+                super::filter::Action::Exit(query) => {
+                    self.set_query(data, query);
+                    self.focus = FocusState::List
+                }
+                super::filter::Action::Edited(query) => self.set_query(data, query),
+                super::filter::Action::Updated(f, query) => {
+                    self.set_query(data, query);
+                    self.focus = FocusState::List;
+                    if let Err(e) = self.filter.remember(&f) {
+                        self.set_error_focus(e.wrap_err("Error saving filter history"));
                     }
                 }
             },
@@ -140,7 +291,7 @@ impl AppTui<'_> {
                     key_event,
                     task_focus,
                     data,
-                    self.table.selected().map(|i| (i, data.get_id(i))),
+                    self.table.selected().map(|i| data.get_id(i)),
                 )? {
                     super::task::Action::Exit => self.focus = FocusState::List,
                     super::task::Action::Unhandled => match key_event.code {
@@ -161,6 +312,10 @@ impl AppTui<'_> {
                 {
                     popup::Action::Exit => return Some(Action::Exit),
                     popup::Action::Unhandled => return Some(Action::Unhandled),
+                    popup::Action::Changed => self.table.clamp(data.len()),
+                    popup::Action::Reloaded => self.reloaded(data),
+                    // From now, or auto_check would check it right away.
+                    popup::Action::Kept(id) => self.timer.start(id, Local::now().naive_local()),
                 }
             }
         }
@@ -168,15 +323,12 @@ impl AppTui<'_> {
     }
 }
 
-impl Default for AppTui<'_> {
-    fn default() -> Self {
-        Self::new(HashMap::new())
-    }
-}
+/// Fits the timer title and an overtime countdown.
+const TIMER_WIDTH: u16 = 12;
 
 pub struct AppWidget<'a, 'b> {
     pub app: Rc<RefCell<AppTui<'a>>>,
-    pub data: &'b mut FilteredData,
+    pub data: &'b FilteredData,
     pub cursor_buf_pos: &'b mut Option<(u16, u16)>,
 }
 
@@ -192,11 +344,22 @@ impl Widget for AppWidget<'_, '_> {
         let [filter_area, area] = [split[0], split[1]];
 
         let app = app.clone();
+        let filter_area = if app.borrow().timer.is_running() {
+            let [filter_area, timer_area] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(TIMER_WIDTH)])
+                    .areas(filter_area);
+            TimerWidget(&app.borrow().timer).render(timer_area, buf);
+            filter_area
+        } else {
+            filter_area
+        };
         let is_focused = matches!(app.borrow().focus, FocusState::Filter);
         FilterWidget {
             tui: &mut app.borrow_mut().filter,
             is_focused,
             cursor_buf_pos,
+            matches: data.len(),
+            total: data.total_len(),
         }
         .render(filter_area, buf);
 
@@ -222,6 +385,12 @@ impl Widget for AppWidget<'_, '_> {
         }
         .render(task_split[1], buf);
 
+        CompletionWidget {
+            tui: &app.filter,
+            filter_area,
+        }
+        .render(area, buf);
+
         if let FocusState::Popup {
             popup: p,
             last_focus: _,
@@ -1,41 +1,150 @@
+use std::borrow::Cow;
+
 use crate::{
-    storage::{keyboard_edit::KeyboardEditable, text_edit::TextOp},
+    filter::{FilterError, FilteredData, Query},
+    storage::{
+        keyboard_edit::KeyboardEditable,
+        text_edit::{LeftRight, TextOp, Unit},
+    },
     tui::{
-        FOCUSED_BORDER, UNFOCUSED_BORDER,
+        FOCUSED_BACKGROUND, FOCUSED_BORDER, UNFOCUSED_BORDER,
+        history::History,
         task::editor::{EditorFocus, EditorTui, EditorWidget},
     },
 };
 use chumsky::text::Char;
 use crop::Rope;
+use eyre::Result;
 use ratatui::{
     crossterm::event::{KeyCode, KeyEvent},
-    style::Style,
-    widgets::{Block, Widget},
+    layout::Rect,
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, List, ListState, StatefulWidget, Widget},
 };
 
+/// Completed by Tab outside of `tag(`.
+const KEYWORDS: &[&str] = &[
+    "age",
+    "and",
+    "asc",
+    "body",
+    "box[",
+    "completed",
+    "contains",
+    "count(",
+    "created",
+    "desc",
+    "duration(",
+    "empty",
+    "false",
+    "field(",
+    "group by tag",
+    "has(",
+    "last",
+    "not",
+    "now",
+    "or",
+    "sort by",
+    "start_of_month",
+    "start_of_week",
+    "started",
+    "tag(",
+    "title",
+    "today",
+    "tomorrow",
+    "true",
+    "voided",
+    "worked(",
+    "yesterday",
+];
+
+/// The editor draws text after its scrollbar and a gap.
+const TEXT_OFFSET: u16 = 2;
+
 pub struct FilterTui {
     editor: EditorTui,
     textbox: KeyboardEditable,
+    /// The text, parsed as it is typed.
+    parsed: Result<Query, FilterError>,
+    history: History,
+    /// Shown under the filter bar while there are several.
+    completion: Option<Completion>,
+    /// The text and the query applied when editing started, to go back to.
+    before_editing: (String, Query),
+}
+
+/// Candidates for the word before the cursor.
+struct Completion {
+    /// Char column the word starts at.
+    start: usize,
+    candidates: Vec<String>,
+    /// Of the candidate in the text, once Tab cycles through them.
+    selected: Option<usize>,
 }
 
 pub enum Action {
-    Exit,
-    Updated(String),
+    /// Esc was pressed, the text is back to what it was before editing and
+    /// the query is the one to apply again.
+    Exit(Query),
+    /// The text changed, and parses.
+    Edited(Query),
+    /// Enter was pressed on text that parses.
+    Updated(String, Query),
 }
 
 impl FilterTui {
-    pub fn new() -> Self {
+    pub fn new(history: History) -> Self {
         Self {
             editor: EditorTui::new(),
             textbox: KeyboardEditable::from_rope(Rope::new(), true),
+            parsed: Ok(Query::default()),
+            history,
+            completion: None,
+            before_editing: (String::new(), Query::default()),
         }
     }
-    pub fn handle_key(&mut self, key_event: KeyEvent) -> Option<Action> {
+
+    /// Remembers what Esc goes back to, `applied` being the query in use.
+    pub fn start_editing(&mut self, applied: &Query) {
+        self.before_editing = (self.text(), applied.clone());
+    }
+    /// `data` has the tags to complete.
+    pub fn handle_key(&mut self, key_event: KeyEvent, data: &FilteredData) -> Option<Action> {
+        let completion = self.completion.take();
         match key_event.code {
-            KeyCode::Enter => Some(Action::Updated(self.textbox.inner().to_string())),
-            KeyCode::Esc => Some(Action::Exit),
+            KeyCode::Enter => {
+                self.history.reset();
+                let query = self.query().ok()?.clone();
+                Some(Action::Updated(self.text(), query))
+            }
+            // Only closes the completions.
+            KeyCode::Esc if completion.is_some() => None,
+            KeyCode::Esc => {
+                self.history.reset();
+                let (text, query) = self.before_editing.clone();
+                self.set_text(text);
+                Some(Action::Exit(query))
+            }
+            KeyCode::Up => {
+                let text = self.text();
+                let recalled = self.history.older(&text)?.to_string();
+                self.set_text(recalled);
+                self.query().ok().cloned().map(Action::Edited)
+            }
+            KeyCode::Down => {
+                let recalled = self.history.newer()?.to_string();
+                self.set_text(recalled);
+                self.query().ok().cloned().map(Action::Edited)
+            }
+            KeyCode::Tab => {
+                let before = self.text();
+                self.complete(completion, data);
+                self.edited(before)
+            }
             _ => {
                 let text_op = KeyboardEditable::map_key_event(key_event)?;
+                let before = self.text();
                 match text_op {
                     TextOp::InsertText(ref cow) => {
                         if !cow.contains(|c: char| c.is_newline()) {
@@ -46,26 +155,162 @@ impl FilterTui {
                         self.textbox.apply_text_op(text_op);
                     }
                 }
-                None
+                self.edited(before)
             }
         }
     }
 
+    /// Adds an applied filter to the history.
+    pub fn remember(&mut self, filter: &str) -> Result<()> {
+        self.history.push(filter)
+    }
+
     pub fn set_text(&mut self, text: String) {
         self.editor.set_text(&text);
         self.textbox = KeyboardEditable::from_rope(text.into(), true);
+        self.check();
+    }
+
+    /// The text parsed, or why it doesn't.
+    pub fn query(&self) -> Result<&Query, &FilterError> {
+        self.parsed.as_ref()
+    }
+
+    fn text(&self) -> String {
+        self.textbox.inner().to_string()
+    }
+
+    fn edited(&mut self, before: String) -> Option<Action> {
+        let text = self.text();
+        if text == before {
+            return None;
+        }
+        self.history.reset();
+        self.check();
+        self.query().ok().cloned().map(Action::Edited)
+    }
+
+    fn check(&mut self) {
+        self.parsed = Query::from_str(&self.text());
+    }
+
+    /// Replaces the word before the cursor by its only candidate, or by what
+    /// all candidates start with. With several, Tab again cycles through them.
+    fn complete(&mut self, completion: Option<Completion>, data: &FilteredData) {
+        let mut completion = match completion {
+            Some(completion) => completion,
+            None => {
+                let Some(completion) = self.candidates(data) else {
+                    return;
+                };
+                if let [only] = completion.candidates.as_slice() {
+                    self.replace_word(completion.start, only);
+                    return;
+                }
+                let prefix = common_prefix(&completion.candidates);
+                self.replace_word(completion.start, &prefix);
+                self.completion = Some(completion);
+                return;
+            }
+        };
+        let next = completion
+            .selected
+            .map_or(0, |i| (i + 1) % completion.candidates.len());
+        completion.selected = Some(next);
+        self.replace_word(completion.start, &completion.candidates[next]);
+        self.completion = Some(completion);
+    }
+
+    /// Tags after `tag(`, keywords elsewhere.
+    fn candidates(&self, data: &FilteredData) -> Option<Completion> {
+        let column = self.textbox.cursor().column;
+        let before: Vec<char> = self.text().chars().take(column).collect();
+        let tag_start = before
+            .iter()
+            .rposition(|c| *c == '(')
+            .filter(|open| {
+                !before[*open..].contains(&')') && before[..*open].ends_with(&['t', 'a', 'g'])
+            })
+            .map(|open| open + 1);
+        let (start, candidates): (usize, Vec<String>) = match tag_start {
+            Some(start) => {
+                let word: String = before[start..].iter().collect();
+                let tags = data.tags();
+                (
+                    start,
+                    tags.into_iter()
+                        .filter(|t| t.starts_with(&word))
+                        .map(|t| format!("{t})"))
+                        .collect(),
+                )
+            }
+            None => {
+                let start = before
+                    .iter()
+                    .rposition(|c| !c.is_alphanumeric() && *c != '_')
+                    .map_or(0, |i| i + 1);
+                let word: String = before[start..].iter().collect();
+                (
+                    start,
+                    KEYWORDS
+                        .iter()
+                        .filter(|k| k.starts_with(&word))
+                        .map(|k| k.to_string())
+                        .collect(),
+                )
+            }
+        };
+        (!candidates.is_empty()).then_some(Completion {
+            start,
+            candidates,
+            selected: None,
+        })
+    }
+
+    /// From `start` to the cursor.
+    fn replace_word(&mut self, start: usize, with: &str) {
+        let column = self.textbox.cursor().column;
+        for _ in start..column {
+            self.textbox.apply_text_op(TextOp::Delete {
+                unit: Unit::Char,
+                dir: LeftRight::Left,
+            });
+        }
+        self.textbox
+            .apply_text_op(TextOp::InsertText(Cow::Owned(with.to_string())));
     }
 }
 
+fn common_prefix(words: &[String]) -> String {
+    let mut prefix: &str = &words[0];
+    for word in &words[1..] {
+        let len = prefix
+            .char_indices()
+            .zip(word.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(word.len()), |((i, _), _)| i);
+        prefix = &prefix[..len];
+    }
+    prefix.to_string()
+}
+
 pub struct FilterWidget<'a> {
     pub tui: &'a mut FilterTui,
     pub is_focused: bool,
     pub cursor_buf_pos: &'a mut Option<(u16, u16)>,
+    /// Shown tasks, of `total`.
+    pub matches: usize,
+    pub total: usize,
 }
 
 impl Widget for FilterWidget<'_> {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
-        let mut filter_block = Block::bordered().title("Filter");
+        let mut filter_block = Block::bordered()
+            .title("Filter")
+            .title(Line::from(format!("{}/{}", self.matches, self.total)).right_aligned());
+        if let Err(error) = &self.tui.parsed {
+            filter_block = filter_block.title_bottom(Line::from(error.message.as_str()).red());
+        }
         filter_block = filter_block.border_style(Style::new().fg(if self.is_focused {
             FOCUSED_BORDER
         } else {
@@ -86,5 +331,184 @@ impl Widget for FilterWidget<'_> {
             },
         }
         .render(area, buf);
+
+        if let Err(error) = &self.tui.parsed {
+            // The end of the input is marked by the cell after it.
+            let text_area = Rect {
+                x: area.x + TEXT_OFFSET,
+                width: area.width.saturating_sub(TEXT_OFFSET),
+                ..area
+            };
+            // Scrolled out of view to the left, if the text is that long.
+            let scrolled = self.tui.editor.column_offset();
+            let start = error.span.start.max(scrolled) - scrolled;
+            let end = error.span.end.max(scrolled) - scrolled;
+            let span = Rect {
+                x: text_area.x.saturating_add(start as u16),
+                width: ((end - start) as u16).max(1),
+                height: 1,
+                ..text_area
+            };
+            buf.set_style(
+                span.intersection(text_area),
+                Style::new().red().underlined(),
+            );
+        }
+    }
+}
+
+/// Lists the completions of the filter bar in `filter_area` below it, within
+/// the area rendered to.
+pub struct CompletionWidget<'a> {
+    pub tui: &'a FilterTui,
+    pub filter_area: Rect,
+}
+
+impl Widget for CompletionWidget<'_> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        const MAX_ROWS: usize = 8;
+        let Some(completion) = &self.tui.completion else {
+            return;
+        };
+        let width = completion
+            .candidates
+            .iter()
+            .map(|c| c.chars().count())
+            .max()
+            .unwrap_or(0);
+        // The candidates line up with the word, inside the border of the list
+        // and the one of the filter bar.
+        let popup_area = Rect {
+            x: self.filter_area.x
+                + TEXT_OFFSET
+                + completion
+                    .start
+                    .saturating_sub(self.tui.editor.column_offset()) as u16,
+            y: self.filter_area.bottom(),
+            width: width as u16 + 2,
+            height: completion.candidates.len().min(MAX_ROWS) as u16 + 2,
+        }
+        .intersection(area);
+        Clear.render(popup_area, buf);
+        let list = List::new(completion.candidates.iter().map(String::as_str))
+            .block(Block::bordered().border_style(Style::new().fg(FOCUSED_BORDER)))
+            .highlight_style(Style::new().bg(FOCUSED_BACKGROUND));
+        let mut state = ListState::default().with_selected(completion.selected);
+        StatefulWidget::render(list, popup_area, buf, &mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::NaiveDateTime;
+    use ratatui::{crossterm::event::KeyModifiers, style::Modifier};
+
+    use super::*;
+    use crate::{
+        storage::{Data, Task},
+        tui::history::HISTORY_FILE,
+    };
+
+    fn filter_tui(dir: &tempfile::TempDir) -> FilterTui {
+        let history = History::load(dir.path().join(HISTORY_FILE))
+            .map_err(|(_, e)| e)
+            .unwrap();
+        FilterTui::new(history)
+    }
+
+    fn data(tags: &[&str]) -> FilteredData {
+        let task = Task::new(
+            "task".to_string(),
+            NaiveDateTime::default(),
+            vec![],
+            tags.iter().map(|t| t.to_string()).collect(),
+            Default::default(),
+            None,
+        );
+        FilteredData::new(Data::new(PathBuf::new(), vec![task]))
+    }
+
+    /// The text after each press of Tab.
+    fn tabs(typed: &str, tags: &[&str], presses: usize) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tui = filter_tui(&dir);
+        let data = data(tags);
+        tui.set_text(typed.to_string());
+        (0..presses)
+            .map(|_| {
+                tui.handle_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE), &data);
+                tui.text()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tab_completes_a_keyword_prefix() {
+        assert_eq!(tabs("created < yest", &[], 1), ["created < yesterday"]);
+        assert_eq!(
+            tabs("sta", &[], 4),
+            ["start", "start_of_month", "start_of_week", "started"]
+        );
+        assert_eq!(tabs("xyz", &[], 1), ["xyz"]);
+    }
+
+    #[test]
+    fn tab_completes_tags_after_tag() {
+        let tags = ["home", "work", "work/bug"];
+        assert_eq!(tabs("tag(h", &tags, 1), ["tag(home)"]);
+        assert_eq!(
+            tabs("tag(wo", &tags, 3),
+            ["tag(work", "tag(work)", "tag(work/bug)"]
+        );
+    }
+
+    #[test]
+    fn error_underline_follows_the_scrolled_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tui = filter_tui(&dir);
+        tui.set_text(format!("title contains \"{}\" and (", "x".repeat(40)));
+        assert!(tui.query().is_err());
+        let area = Rect::new(0, 0, 20, 3);
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        FilterWidget {
+            tui: &mut tui,
+            is_focused: false,
+            cursor_buf_pos: &mut None,
+            matches: 0,
+            total: 0,
+        }
+        .render(area, &mut buf);
+        // Past the end of the input, which is scrolled to the right edge.
+        let underlined: Vec<u16> = (0..area.width)
+            .filter(|x| buf[(*x, 1)].modifier.contains(Modifier::UNDERLINED))
+            .collect();
+        assert_eq!(underlined, [area.width - 2]);
+    }
+
+    #[test]
+    fn esc_goes_back_to_the_text_before_editing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tui = filter_tui(&dir);
+        let data = data(&[]);
+        tui.set_text("tag(a)".to_string());
+        tui.start_editing(data.query());
+        for c in " and body".chars() {
+            let action = tui.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), &data);
+            assert!(!matches!(action, Some(Action::Exit(_))));
+        }
+        assert_eq!(tui.text(), "tag(a) and body");
+        let mut data = data;
+        let Some(Action::Exit(query)) =
+            tui.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE), &data)
+        else {
+            panic!("Esc didn't exit");
+        };
+        // The query in use before, showing the untagged task.
+        data.set_query(query);
+        assert_eq!(data.len(), 1);
+        assert_eq!(tui.text(), "tag(a)");
+        assert!(tui.query().is_ok());
     }
 }
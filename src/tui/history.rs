@@ -0,0 +1,143 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use eyre::{Context, Result};
+
+/// Name of the file in the data folder. Starting with a dot keeps it from
+/// being read as a task.
+pub const HISTORY_FILE: &str = ".filter_history";
+
+/// Filters kept, the oldest are dropped first.
+const MAX_ENTRIES: usize = 100;
+
+/// Filters applied in the filter bar, oldest first, one per line in a file.
+pub struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+    /// Of the recalled entry, if one is.
+    position: Option<usize>,
+    /// What was typed before recalling entries.
+    draft: String,
+}
+
+impl History {
+    /// A missing file is an empty history.
+    pub fn load(path: PathBuf) -> Result<Self, (Self, eyre::Report)> {
+        let mut history = Self {
+            path,
+            entries: vec![],
+            position: None,
+            draft: String::new(),
+        };
+        match fs::read_to_string(&history.path) {
+            Ok(text) => {
+                history.entries = text.lines().map(str::to_string).collect();
+                Ok(history)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(history),
+            Err(e) => {
+                let e =
+                    eyre::Report::new(e).wrap_err(format!("reading '{}'", history.path.display()));
+                Err((history, e))
+            }
+        }
+    }
+
+    /// Adds `filter` as the newest entry and writes the history.
+    pub fn push(&mut self, filter: &str) -> Result<()> {
+        self.position = None;
+        if filter.trim().is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|e| e != filter);
+        self.entries.push(filter.to_string());
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+        let mut text = self.entries.join("\n");
+        text.push('\n');
+        fs::write(&self.path, text).wrap_err_with(|| format!("writing '{}'", self.path.display()))
+    }
+
+    /// The entry before the recalled one, or the newest. `current` is kept to
+    /// come back to.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let i = match self.position {
+            None => {
+                self.draft = current.to_string();
+                self.entries.len().checked_sub(1)?
+            }
+            Some(i) => i.checked_sub(1)?,
+        };
+        self.position = Some(i);
+        Some(&self.entries[i])
+    }
+
+    /// The entry after the recalled one, or what was typed before recalling.
+    pub fn newer(&mut self) -> Option<&str> {
+        let i = self.position?;
+        if i + 1 < self.entries.len() {
+            self.position = Some(i + 1);
+            Some(&self.entries[i + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Stops recalling, after the text was edited.
+    pub fn reset(&mut self) {
+        self.position = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> (tempfile::TempDir, History) {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::load(dir.path().join(HISTORY_FILE))
+            .map_err(|(_, e)| e)
+            .unwrap();
+        (dir, history)
+    }
+
+    #[test]
+    fn repeats_move_to_the_newest() {
+        let (_dir, mut history) = empty();
+        for filter in ["a", "b", "a", " "] {
+            history.push(filter).unwrap();
+        }
+        assert_eq!(history.entries, ["b", "a"]);
+    }
+
+    #[test]
+    fn oldest_are_dropped_past_the_cap() {
+        let (_dir, mut history) = empty();
+        for i in 0..MAX_ENTRIES + 5 {
+            history.push(&i.to_string()).unwrap();
+        }
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries[0], "5");
+        assert_eq!(
+            history.entries[MAX_ENTRIES - 1],
+            (MAX_ENTRIES + 4).to_string()
+        );
+    }
+
+    #[test]
+    fn entries_are_kept_across_loads() {
+        let (dir, mut history) = empty();
+        history.push("tag(work)").unwrap();
+        history.push("completed = false").unwrap();
+        let mut history = History::load(dir.path().join(HISTORY_FILE))
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(history.entries, ["tag(work)", "completed = false"]);
+        assert_eq!(history.older("typed"), Some("completed = false"));
+        assert_eq!(history.older("typed"), Some("tag(work)"));
+        assert_eq!(history.older("typed"), None);
+        assert_eq!(history.newer(), Some("completed = false"));
+        assert_eq!(history.newer(), Some("typed"));
+        assert_eq!(history.newer(), None);
+    }
+}
@@ -2,9 +2,11 @@ use ratatui::style::Color;
 
 pub mod app;
 mod filter;
+pub mod history;
 pub mod popup;
 mod table;
 pub mod task;
+pub mod timer;
 
 pub const FOCUSED_BORDER: Color = Color::LightBlue;
 pub const FOCUSED_BACKGROUND: Color = Color::Blue;
@@ -1,3 +1,4 @@
+use chrono::Local;
 use ratatui::{
     crossterm::event::{KeyCode, KeyEvent},
     widgets::Widget,
@@ -5,8 +6,12 @@ use ratatui::{
 
 use crate::{
     FocusState, PopupEnum,
-    filter::FilteredData,
-    tui::popup::dialog::{AddAction, ErrorAction, ErrorDialog, Popup, SaveAction},
+    filter::{FilteredData, TaskID},
+    storage::Resolution,
+    tui::popup::dialog::{
+        AddAction, DeleteAction, ErrorAction, ErrorDialog, Popup, ReportAction, SaveAction,
+        StaleAction, TrashAction, VoidAction,
+    },
 };
 
 pub struct PopupTui {}
@@ -14,6 +19,12 @@ pub struct PopupTui {}
 pub enum Action {
     Unhandled,
     Exit,
+    /// Tasks were removed or added.
+    Changed,
+    /// Tasks were replaced by what is on disk.
+    Reloaded,
+    /// A box left started is kept, and counted down again from now.
+    Kept(TaskID),
 }
 
 impl PopupTui {
@@ -73,6 +84,87 @@ impl PopupTui {
             PopupEnum::Error(error) => match error.handle_key(key_event) {
                 ErrorAction::Okay => *focus = *last_focus.clone(),
             },
+            PopupEnum::Delete(delete) => match delete.handle_key(key_event) {
+                Some(DeleteAction::Delete(id)) => {
+                    if let Err(e) = data.remove(id) {
+                        *focus = FocusState::Popup {
+                            popup: PopupEnum::Error(ErrorDialog::from_error_focus(&e)),
+                            last_focus: last_focus.clone(),
+                        };
+                        return None;
+                    }
+                    *focus = *last_focus.clone();
+                    return Some(Action::Changed);
+                }
+                Some(DeleteAction::Cancel) => *focus = *last_focus.clone(),
+                None => {}
+            },
+            PopupEnum::Trash(trash) => match trash.handle_key(key_event) {
+                Some(TrashAction::Restore(trashed)) => {
+                    if let Err(e) = data.restore(&trashed) {
+                        *focus = FocusState::Popup {
+                            popup: PopupEnum::Error(ErrorDialog::from_error_focus(&e)),
+                            last_focus: focus.clone().into(),
+                        };
+                    } else {
+                        trash.remove_selected();
+                    }
+                    return Some(Action::Changed);
+                }
+                Some(TrashAction::Exit) => *focus = *last_focus.clone(),
+                None => {}
+            },
+            PopupEnum::Void(void) => match void.handle_key(key_event) {
+                Some(VoidAction::Void(id, reason)) => {
+                    data.void_box(id, Local::now().naive_local(), reason);
+                    *focus = *last_focus.clone();
+                }
+                Some(VoidAction::Cancel) => *focus = *last_focus.clone(),
+                None => {}
+            },
+            PopupEnum::Stale(stale) => {
+                match stale.handle_key(key_event) {
+                    Some(StaleAction::Check(id, end)) => {
+                        data.check_started(id, end);
+                    }
+                    Some(StaleAction::Void(id, end)) => {
+                        data.void_box(id, end, None);
+                    }
+                    Some(StaleAction::Keep(id)) => {
+                        stale.remove_selected();
+                        if stale.is_empty() {
+                            *focus = *last_focus.clone();
+                        }
+                        return Some(Action::Kept(id));
+                    }
+                    // The timer counts down one box, the last started.
+                    Some(StaleAction::Exit) => {
+                        let latest = stale.latest();
+                        *focus = *last_focus.clone();
+                        return latest.map(Action::Kept);
+                    }
+                    None => return None,
+                }
+                stale.remove_selected();
+                if stale.is_empty() {
+                    *focus = *last_focus.clone();
+                }
+            }
+            PopupEnum::Report(report) => match report.handle_key(key_event) {
+                Some(ReportAction::Update) => report.update(data),
+                Some(ReportAction::Exit) => *focus = *last_focus.clone(),
+                None => {}
+            },
+            PopupEnum::Conflict(conflict) => {
+                if let Some(resolution) = conflict.handle_key(key_event) {
+                    let reloaded = !matches!(resolution, Resolution::KeepMine);
+                    data.resolve_conflict(conflict.conflict.clone(), resolution);
+                    *focus = *last_focus.clone();
+                    if reloaded {
+                        return Some(Action::Reloaded);
+                    }
+                }
+            }
         }
         None
     }
@@ -86,6 +178,12 @@ impl Widget for PopupWidget<'_> {
             PopupEnum::WritePopup(d) => d.render(area, buf),
             PopupEnum::AddNew(d) => d.render(area, buf),
             PopupEnum::Error(d) => d.render(area, buf),
+            PopupEnum::Conflict(d) => d.render(area, buf),
+            PopupEnum::Delete(d) => d.render(area, buf),
+            PopupEnum::Trash(d) => d.render(area, buf),
+            PopupEnum::Void(d) => d.render(area, buf),
+            PopupEnum::Stale(d) => d.render(area, buf),
+            PopupEnum::Report(d) => d.render(area, buf),
         }
     }
 }
@@ -93,7 +191,7 @@ impl Widget for PopupWidget<'_> {
 pub mod dialog {
     use std::collections::HashSet;
 
-    use chrono::Local;
+    use chrono::{Local, NaiveDateTime, TimeDelta};
     use ratatui::{
         buffer::Buffer,
         crossterm::event::{KeyCode, KeyEvent},
@@ -103,7 +201,11 @@ pub mod dialog {
     };
     use tui_textarea::TextArea;
 
-    use crate::storage::Task;
+    use crate::{
+        filter::FilteredData,
+        report::{Format, Group, Report},
+        storage::{Conflict, Resolution, Task, TaskID, Trashed},
+    };
 
     pub trait Popup {
         const TITLE: &str;
@@ -222,6 +324,62 @@ pub mod dialog {
         }
     }
 
+    #[derive(Clone, Debug)]
+    pub struct VoidDialog<'a> {
+        id: TaskID,
+        textbox: Box<TextArea<'a>>,
+    }
+    pub enum VoidAction {
+        /// With the reason, if one was given.
+        Void(TaskID, Option<String>),
+        Cancel,
+    }
+    impl VoidDialog<'_> {
+        pub fn new(id: TaskID) -> Self {
+            let mut textbox = TextArea::default();
+            textbox.set_placeholder_text("reason (optional), Enter to void, ESC to cancel");
+            Self {
+                id,
+                textbox: textbox.into(),
+            }
+        }
+    }
+
+    impl Popup for VoidDialog<'_> {
+        const TITLE: &'static str = "Void Started Box";
+        type Action = Option<VoidAction>;
+
+        fn draw_in_rect(&self, area: Rect, buf: &mut Buffer) {
+            self.textbox.as_ref().render(area, buf);
+        }
+
+        fn get_dimensions(&self, available_area: Rect) -> (u16, u16) {
+            (available_area.width, available_area.height)
+        }
+
+        fn handle_key(&mut self, key_event: KeyEvent) -> Self::Action {
+            match key_event.code {
+                KeyCode::Enter => {
+                    let reason = self.textbox.lines().join(" ").trim().to_string();
+                    Some(VoidAction::Void(
+                        self.id,
+                        Some(reason).filter(|r| !r.is_empty()),
+                    ))
+                }
+                KeyCode::Esc => Some(VoidAction::Cancel),
+                _ => {
+                    self.textbox.input(key_event);
+                    None
+                }
+            }
+        }
+    }
+    impl Widget for &VoidDialog<'_> {
+        fn render(self, area: Rect, buf: &mut Buffer) {
+            render(self, area, buf)
+        }
+    }
+
     #[derive(Debug, Clone)]
     pub struct ErrorDialog {
         pub error: String,
@@ -263,4 +421,428 @@ pub mod dialog {
             }
         }
     }
+
+    #[derive(Debug, Clone)]
+    pub struct ConflictDialog {
+        pub conflict: Conflict,
+        text: String,
+    }
+    impl ConflictDialog {
+        pub fn new(conflict: Conflict) -> Self {
+            let name = conflict
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let text = if conflict.theirs.is_some() {
+                format!(
+                    "'{name}' changed on disk and has unsaved edits\n\
+                     keep mine(k), take theirs(t), merge body(m)\n\
+                     ESC to keep mine"
+                )
+            } else {
+                format!(
+                    "'{name}' was removed on disk and has unsaved edits\n\
+                     keep mine(k), remove(t)\n\
+                     ESC to keep mine"
+                )
+            };
+            Self { conflict, text }
+        }
+    }
+    impl Popup for ConflictDialog {
+        const TITLE: &'static str = "Conflict Popup";
+        type Action = Option<Resolution>;
+
+        fn draw_in_rect(&self, area: Rect, buf: &mut Buffer) {
+            Text::raw(self.text.as_str()).render(area, buf);
+        }
+
+        fn get_dimensions(&self, _: Rect) -> (u16, u16) {
+            (
+                self.text.lines().map(|l| l.chars().count()).max().unwrap() as u16,
+                self.text.lines().count() as u16,
+            )
+        }
+
+        fn handle_key(&mut self, key_event: KeyEvent) -> Self::Action {
+            match key_event.code {
+                KeyCode::Char('k') | KeyCode::Esc => Some(Resolution::KeepMine),
+                KeyCode::Char('t') => Some(Resolution::TakeTheirs),
+                KeyCode::Char('m') if self.conflict.theirs.is_some() => Some(Resolution::Merge),
+                _ => None,
+            }
+        }
+    }
+    impl Widget for &ConflictDialog {
+        fn render(self, area: Rect, buf: &mut Buffer) {
+            render(self, area, buf)
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct DeleteDialog {
+        id: TaskID,
+        text: String,
+    }
+    pub enum DeleteAction {
+        Delete(TaskID),
+        Cancel,
+    }
+    impl DeleteDialog {
+        pub fn new(id: TaskID, title: &str) -> Self {
+            Self {
+                id,
+                text: format!("move '{title}' to the trash?\nyes(y), no(n)\nESC to cancel"),
+            }
+        }
+    }
+    impl Popup for DeleteDialog {
+        const TITLE: &'static str = "Delete Popup";
+        type Action = Option<DeleteAction>;
+
+        fn draw_in_rect(&self, area: Rect, buf: &mut Buffer) {
+            Text::raw(self.text.as_str()).render(area, buf);
+        }
+
+        fn get_dimensions(&self, _: Rect) -> (u16, u16) {
+            (
+                self.text.lines().map(|l| l.chars().count()).max().unwrap() as u16,
+                self.text.lines().count() as u16,
+            )
+        }
+
+        fn handle_key(&mut self, key_event: KeyEvent) -> Self::Action {
+            match key_event.code {
+                KeyCode::Char('y') => Some(DeleteAction::Delete(self.id)),
+                KeyCode::Char('n') | KeyCode::Esc => Some(DeleteAction::Cancel),
+                _ => None,
+            }
+        }
+    }
+    impl Widget for &DeleteDialog {
+        fn render(self, area: Rect, buf: &mut Buffer) {
+            render(self, area, buf)
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct TrashDialog {
+        trashed: Vec<Trashed>,
+        selected: usize,
+    }
+    pub enum TrashAction {
+        Restore(Box<Trashed>),
+        Exit,
+    }
+    const TRASH_HELP: &str = "restore(Enter), ESC to close";
+    impl TrashDialog {
+        pub fn new(trashed: Vec<Trashed>) -> Self {
+            Self {
+                trashed,
+                selected: 0,
+            }
+        }
+        pub fn remove_selected(&mut self) {
+            if self.selected < self.trashed.len() {
+                self.trashed.remove(self.selected);
+            }
+            self.selected = self.selected.min(self.trashed.len().saturating_sub(1));
+        }
+        fn lines(&self) -> Vec<String> {
+            if self.trashed.is_empty() {
+                return vec!["trash is empty".to_string()];
+            }
+            self.trashed
+                .iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    format!(
+                        "{} {}  {}",
+                        if i == self.selected { ">" } else { " " },
+                        t.deleted.format("%Y-%m-%d %H:%M"),
+                        t.title
+                    )
+                })
+                .collect()
+        }
+    }
+    impl Popup for TrashDialog {
+        const TITLE: &'static str = "Trash";
+        type Action = Option<TrashAction>;
+
+        fn draw_in_rect(&self, area: Rect, buf: &mut Buffer) {
+            let height = area.height.saturating_sub(1) as usize;
+            // Scroll the selection into view.
+            let skip = (self.selected + 1).saturating_sub(height);
+            let mut lines: Vec<_> = self.lines().into_iter().skip(skip).take(height).collect();
+            lines.push(TRASH_HELP.to_string());
+            Text::raw(lines.join("\n")).render(area, buf);
+        }
+
+        fn get_dimensions(&self, available_area: Rect) -> (u16, u16) {
+            let lines = self.lines();
+            let width = lines
+                .iter()
+                .chain([&TRASH_HELP.to_string()])
+                .map(|l| l.chars().count())
+                .max()
+                .unwrap() as u16;
+            (
+                width.min(available_area.width),
+                (lines.len() as u16 + 1).min(available_area.height),
+            )
+        }
+
+        fn handle_key(&mut self, key_event: KeyEvent) -> Self::Action {
+            match key_event.code {
+                KeyCode::Down if self.selected + 1 < self.trashed.len() => {
+                    self.selected += 1;
+                    None
+                }
+                KeyCode::Up => {
+                    self.selected = self.selected.saturating_sub(1);
+                    None
+                }
+                KeyCode::Enter => self
+                    .trashed
+                    .get(self.selected)
+                    .map(|t| TrashAction::Restore(t.clone().into())),
+                KeyCode::Esc => Some(TrashAction::Exit),
+                _ => None,
+            }
+        }
+    }
+    impl Widget for &TrashDialog {
+        fn render(self, area: Rect, buf: &mut Buffer) {
+            render(self, area, buf)
+        }
+    }
+
+    /// A box left started since before the app was last closed.
+    #[derive(Debug, Clone)]
+    pub struct Stale {
+        pub id: TaskID,
+        pub title: String,
+        pub start: NaiveDateTime,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct StaleDialog {
+        stale: Vec<Stale>,
+        selected: usize,
+        length: TimeDelta,
+    }
+    pub enum StaleAction {
+        Check(TaskID, NaiveDateTime),
+        Void(TaskID, NaiveDateTime),
+        Keep(TaskID),
+        Exit,
+    }
+    const STALE_HELP: &str = "check(c), void(v), keep(k), ESC to keep all";
+    impl StaleDialog {
+        pub fn new(stale: Vec<Stale>, length: TimeDelta) -> Self {
+            Self {
+                stale,
+                selected: 0,
+                length,
+            }
+        }
+        pub fn remove_selected(&mut self) {
+            if self.selected < self.stale.len() {
+                self.stale.remove(self.selected);
+            }
+            self.selected = self.selected.min(self.stale.len().saturating_sub(1));
+        }
+        pub fn is_empty(&self) -> bool {
+            self.stale.is_empty()
+        }
+        /// Of the boxes not yet checked or voided.
+        pub fn latest(&self) -> Option<TaskID> {
+            self.stale.iter().max_by_key(|s| s.start).map(|s| s.id)
+        }
+        fn lines(&self) -> Vec<String> {
+            self.stale
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    format!(
+                        "{} started {}  {}",
+                        if i == self.selected { ">" } else { " " },
+                        s.start.format("%Y-%m-%d %H:%M"),
+                        s.title
+                    )
+                })
+                .collect()
+        }
+    }
+    impl Popup for StaleDialog {
+        const TITLE: &'static str = "Unfinished Boxes";
+        type Action = Option<StaleAction>;
+
+        fn draw_in_rect(&self, area: Rect, buf: &mut Buffer) {
+            let height = area.height.saturating_sub(1) as usize;
+            // Scroll the selection into view.
+            let skip = (self.selected + 1).saturating_sub(height);
+            let mut lines: Vec<_> = self.lines().into_iter().skip(skip).take(height).collect();
+            lines.push(STALE_HELP.to_string());
+            Text::raw(lines.join("\n")).render(area, buf);
+        }
+
+        fn get_dimensions(&self, available_area: Rect) -> (u16, u16) {
+            let lines = self.lines();
+            let width = lines
+                .iter()
+                .chain([&STALE_HELP.to_string()])
+                .map(|l| l.chars().count())
+                .max()
+                .unwrap() as u16;
+            (
+                width.min(available_area.width),
+                (lines.len() as u16 + 1).min(available_area.height),
+            )
+        }
+
+        fn handle_key(&mut self, key_event: KeyEvent) -> Self::Action {
+            // Boxes are finished when the pomodoro would have ended.
+            let end = |s: &Stale| s.start + self.length;
+            match key_event.code {
+                KeyCode::Down if self.selected + 1 < self.stale.len() => {
+                    self.selected += 1;
+                    None
+                }
+                KeyCode::Up => {
+                    self.selected = self.selected.saturating_sub(1);
+                    None
+                }
+                KeyCode::Char('c') => self
+                    .stale
+                    .get(self.selected)
+                    .map(|s| StaleAction::Check(s.id, end(s))),
+                KeyCode::Char('v') => self
+                    .stale
+                    .get(self.selected)
+                    .map(|s| StaleAction::Void(s.id, end(s))),
+                KeyCode::Char('k') => self
+                    .stale
+                    .get(self.selected)
+                    .map(|s| StaleAction::Keep(s.id)),
+                KeyCode::Esc => Some(StaleAction::Exit),
+                _ => None,
+            }
+        }
+    }
+    impl Widget for &StaleDialog {
+        fn render(self, area: Rect, buf: &mut Buffer) {
+            render(self, area, buf)
+        }
+    }
+
+    /// Boxes checked by the visible tasks.
+    #[derive(Debug, Clone)]
+    pub struct ReportDialog {
+        group: Group,
+        /// Only boxes checked within this many days, if set.
+        days: Option<i64>,
+        lines: Vec<String>,
+        scroll: usize,
+    }
+    pub enum ReportAction {
+        /// The report needs to be recalculated.
+        Update,
+        Exit,
+    }
+    /// Ranges cycled through, in days.
+    const REPORT_RANGES: [Option<i64>; 3] = [None, Some(7), Some(30)];
+    impl ReportDialog {
+        pub fn new(data: &FilteredData) -> Self {
+            let mut out = Self {
+                group: Group::Tag,
+                days: None,
+                lines: vec![],
+                scroll: 0,
+            };
+            out.update(data);
+            out
+        }
+        pub fn update(&mut self, data: &FilteredData) {
+            let from = self
+                .days
+                .map(|days| Local::now().naive_local() - TimeDelta::days(days));
+            let report = Report::new(data, self.group, from, None);
+            self.lines = report
+                .render(Format::Table)
+                .lines()
+                .map(str::to_string)
+                .collect();
+            self.scroll = self.scroll.min(self.lines.len().saturating_sub(1));
+        }
+        fn help(&self) -> String {
+            let range = match self.days {
+                Some(days) => format!("last {days} days"),
+                None => "all time".to_string(),
+            };
+            format!("group(g), range(r): {range}, ESC to close")
+        }
+    }
+    impl Popup for ReportDialog {
+        const TITLE: &'static str = "Report";
+        type Action = Option<ReportAction>;
+
+        fn draw_in_rect(&self, area: Rect, buf: &mut Buffer) {
+            let height = area.height.saturating_sub(1) as usize;
+            let mut lines: Vec<_> = self
+                .lines
+                .iter()
+                .skip(self.scroll)
+                .take(height)
+                .cloned()
+                .collect();
+            lines.push(self.help());
+            Text::raw(lines.join("\n")).render(area, buf);
+        }
+
+        fn get_dimensions(&self, available_area: Rect) -> (u16, u16) {
+            let width = self
+                .lines
+                .iter()
+                .chain([&self.help()])
+                .map(|l| l.chars().count())
+                .max()
+                .unwrap() as u16;
+            (
+                width.min(available_area.width),
+                (self.lines.len() as u16 + 1).min(available_area.height),
+            )
+        }
+
+        fn handle_key(&mut self, key_event: KeyEvent) -> Self::Action {
+            match key_event.code {
+                KeyCode::Down if self.scroll + 1 < self.lines.len() => {
+                    self.scroll += 1;
+                    None
+                }
+                KeyCode::Up => {
+                    self.scroll = self.scroll.saturating_sub(1);
+                    None
+                }
+                KeyCode::Char('g') => {
+                    self.group = self.group.next();
+                    Some(ReportAction::Update)
+                }
+                KeyCode::Char('r') => {
+                    let i = REPORT_RANGES.iter().position(|d| *d == self.days).unwrap();
+                    self.days = REPORT_RANGES[(i + 1) % REPORT_RANGES.len()];
+                    Some(ReportAction::Update)
+                }
+                KeyCode::Esc => Some(ReportAction::Exit),
+                _ => None,
+            }
+        }
+    }
+    impl Widget for &ReportDialog {
+        fn render(self, area: Rect, buf: &mut Buffer) {
+            render(self, area, buf)
+        }
+    }
 }
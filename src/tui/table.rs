@@ -1,18 +1,17 @@
-use std::process::Command;
-
 use chrono::Local;
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Cell, HighlightSpacing, Row, Table, TableState, Widget};
 
 use crate::FocusState;
-use crate::filter::FilteredData;
-use crate::storage::BoxState;
+use crate::filter::{FilteredData, TaskID};
+use crate::storage::{BoxState, Date};
 use crate::tui::{FOCUSED_BACKGROUND, FOCUSED_BORDER, UNFOCUSED_BORDER};
 
 const CHECK: &str = " ✔";
 const STARTED: &str = "🌟";
 const EMPTY: &str = " -";
+const VOIDED: &str = " ✘";
 
 pub struct TableTui {
     table_state: TableState,
@@ -21,6 +20,14 @@ pub struct TableTui {
 pub enum Action {
     Unhandled,
     Add,
+    Delete,
+    Trash,
+    Void,
+    Report,
+    /// A box was started on the task.
+    Started(TaskID, Date),
+    /// The started box was checked.
+    Checked(Date),
 }
 
 impl TableTui {
@@ -34,62 +41,41 @@ impl TableTui {
         data: &mut FilteredData,
         key_event: KeyEvent,
     ) -> Option<Action> {
-        let i = self.table_state.selected();
+        let id = self.table_state.selected().map(|i| data.get_id(i));
         match key_event.code {
             KeyCode::Down => self.next_row(data),
             KeyCode::Up => self.prev_row(data),
             KeyCode::Char('n') => {
-                if let Some(i) = i {
-                    data.push_box(i)
+                if let Some(id) = id {
+                    data.push_box(id)
                 }
             }
             KeyCode::Char('N') => {
-                if let Some(i) = i {
-                    match data.step_box_state(i, Local::now().naive_local()) {
-                        Some(BoxState::Started) => {
-                            std::thread::spawn(|| {
-                                Command::new("/usr/bin/osascript")
-                                    .args([
-                                        "-e",
-                                        r#"tell application "Menubar Countdown"
-                                    	set hours to "0"
-                                        set minutes to "25"
-                                     	set seconds to "0"
-                                        set play notification sound to false
-                                        set repeat alert sound to false
-                                    	start timer
-                                    end tell"#,
-                                    ])
-                                    .output()
-                                    .unwrap();
-                            });
-                        }
-                        Some(BoxState::Checked(_)) => {
-                            std::thread::spawn(|| {
-                                Command::new("/usr/bin/osascript")
-                                    .args([
-                                        "-e",
-                                        r#"tell application "Menubar Countdown" to stop timer"#,
-                                    ])
-                                    .output()
-                                    .unwrap();
-                            });
+                if let Some(id) = id {
+                    match data.step_box_state(id, Local::now().naive_local()) {
+                        Some(BoxState::Started(Some(start))) => {
+                            return Some(Action::Started(id, start));
                         }
+                        Some(BoxState::Checked { end, .. }) => return Some(Action::Checked(end)),
                         _ => {}
                     }
                 };
             }
             KeyCode::Backspace => {
-                if let Some(i) = self.table_state.selected() {
-                    data.remove_empty_state(i);
+                if let Some(id) = id {
+                    data.remove_empty_state(id);
                 }
             }
             KeyCode::Char('F') => {
-                if let Some(i) = self.table_state.selected() {
-                    data.set_completed(i, Some(Local::now().naive_local()));
+                if let Some(id) = id {
+                    data.set_completed(id, Some(Local::now().naive_local()));
                 }
             }
             KeyCode::Char('A') => return Some(Action::Add),
+            KeyCode::Char('D') if id.is_some() => return Some(Action::Delete),
+            KeyCode::Char('T') => return Some(Action::Trash),
+            KeyCode::Char('V') if id.is_some() => return Some(Action::Void),
+            KeyCode::Char('R') => return Some(Action::Report),
             _ => return Some(Action::Unhandled),
         };
         None
@@ -132,6 +118,15 @@ impl TableTui {
     pub fn set_selected(&mut self, index: usize) {
         *self.table_state.selected_mut() = Some(index);
     }
+    /// Keeps the selection within a table of `len` rows.
+    pub fn clamp(&mut self, len: usize) {
+        let selected = match self.table_state.selected() {
+            _ if len == 0 => None,
+            Some(i) => Some(i.min(len - 1)),
+            None => Some(0),
+        };
+        self.table_state.select(selected);
+    }
 }
 
 pub struct TableWidget<'a, 'b>(
@@ -152,7 +147,7 @@ impl Widget for TableWidget<'_, '_> {
             Constraint::Min(max_boxes.try_into().unwrap()),
             Constraint::Min(1),
         ];
-        let rows = data
+        let mut rows = data
             .iter()
             .map(|t| {
                 let text_cell = Cell::from(t.title());
@@ -168,8 +163,9 @@ impl Widget for TableWidget<'_, '_> {
                             .iter()
                             .rev()
                             .map(|b| match b {
-                                BoxState::Checked(_) => CHECK,
-                                BoxState::Started => STARTED,
+                                BoxState::Checked { .. } => CHECK,
+                                BoxState::Started(_) => STARTED,
+                                BoxState::Voided { .. } => VOIDED,
                                 BoxState::Empty => EMPTY,
                             })
                             .collect::<String>(),
@@ -181,6 +177,12 @@ impl Widget for TableWidget<'_, '_> {
                     .style(Style::new().bg(Color::Reset))
             })
             .collect::<Vec<_>>();
+        // Headers go above their groups, from the last so that the starts of
+        // earlier groups stay valid.
+        for group in data.groups().iter().rev() {
+            let header = format!("{} ({})", group.name, group.len);
+            rows.insert(group.start, Row::new(vec![Cell::from(header.bold())]));
+        }
         let style = if matches!(focus, FocusState::List) {
             FOCUSED_BORDER
         } else {
@@ -206,6 +208,11 @@ impl Widget for TableWidget<'_, '_> {
                 .bottom_margin(1),
             );
 
-        StatefulWidget::render(t, area, buf, &mut table.table_state);
+        // The selection counts tasks, the table counts headers too.
+        let headers_before = |i: usize| data.groups().iter().filter(|g| g.start <= i).count();
+        let mut state = table.table_state.clone();
+        state.select(state.selected().map(|i| i + headers_before(i)));
+        StatefulWidget::render(t, area, buf, &mut state);
+        *table.table_state.offset_mut() = state.offset();
     }
 }
@@ -3,18 +3,21 @@ use ratatui::prelude::*;
 use ratatui::widgets::{Block, Widget};
 
 use crate::filter::{FilteredData, TaskID};
-use crate::storage::{BoxState, Task};
+use crate::storage::{BoxState, Node};
 use crate::tui::task::editor::{EditorFocus, EditorTui, EditorWidget};
 use crate::tui::task::tags::{TagsTui, TagsWidget};
+use crate::tui::task::title::{TitleTui, TitleWidget};
 use crate::tui::{FOCUSED_BORDER, LOCKED_EDITOR_BORDER, UNFOCUSED_BORDER};
 
 pub mod editor;
 mod scrollbar;
 mod tags;
+mod title;
 
 pub struct TaskTui {
     editor: EditorTui,
     tags: TagsTui,
+    title: TitleTui,
 }
 
 pub enum Action {
@@ -27,14 +30,19 @@ impl TaskTui {
         Self {
             editor: EditorTui::new(),
             tags: TagsTui::new(),
+            title: TitleTui::new(),
         }
     }
+    pub fn reloaded(&mut self) {
+        self.tags.clear();
+        self.title.clear();
+    }
     pub fn handle_key_event(
         &mut self,
         key_event: KeyEvent,
         focus: &mut TaskFocus,
         data: &mut FilteredData,
-        task: Option<(usize, TaskID)>,
+        task: Option<TaskID>,
     ) -> Option<Action> {
         match focus {
             TaskFocus::Boxes => {}
@@ -42,12 +50,15 @@ impl TaskTui {
                 self.editor.handle_key_event(
                     key_event,
                     editor_focus,
-                    task.and_then(|(_, id)| data.get_mut(id)),
+                    task.and_then(|id| data.get_mut(id)),
                 )?;
             }
             TaskFocus::Tags(focus) => {
                 self.tags.handle_key(key_event, focus, data, task)?;
             }
+            TaskFocus::Title(focus) => {
+                self.title.handle_key(key_event, focus, data, task)?;
+            }
         }
 
         let i = focus.to_i8();
@@ -64,28 +75,34 @@ impl TaskTui {
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TaskFocus {
+    Title(EditorFocus),
     Context(EditorFocus),
     Tags(EditorFocus),
     Boxes,
 }
-const TASK_COUNT: i8 = 3;
+const TASK_COUNT: i8 = 4;
 
 impl TaskFocus {
     fn to_i8(self) -> i8 {
         match self {
-            TaskFocus::Tags(_) => 0,
-            TaskFocus::Context(_) => 1,
-            TaskFocus::Boxes => 2,
+            TaskFocus::Title(_) => 0,
+            TaskFocus::Tags(_) => 1,
+            TaskFocus::Context(_) => 2,
+            TaskFocus::Boxes => 3,
         }
     }
     fn from_i8_wrapped(v: i8) -> Self {
         match v.rem_euclid(TASK_COUNT) {
-            0 => TaskFocus::Tags(EditorFocus::default()),
-            1 => TaskFocus::Context(EditorFocus::default()),
-            2 => TaskFocus::Boxes,
+            0 => TaskFocus::Title(EditorFocus::default()),
+            1 => TaskFocus::Tags(EditorFocus::default()),
+            2 => TaskFocus::Context(EditorFocus::default()),
+            3 => TaskFocus::Boxes,
             _ => unreachable!(),
         }
     }
+    pub fn title_locked() -> Self {
+        Self::Title(EditorFocus::Locked)
+    }
     pub fn tags_locked() -> Self {
         Self::Tags(EditorFocus::Locked)
     }
@@ -107,11 +124,17 @@ impl TaskFocus {
             _ => None,
         }
     }
+    pub fn as_title(self) -> Option<EditorFocus> {
+        match self {
+            TaskFocus::Title(editor_focus) => Some(editor_focus),
+            _ => None,
+        }
+    }
 }
 
 pub struct TaskWidget<'a, 'b> {
     pub task: &'a mut TaskTui,
-    pub data: &'b mut FilteredData,
+    pub data: &'b FilteredData,
     pub id: Option<TaskID>,
     pub focus: Option<TaskFocus>,
     pub cursor_buf_pos: &'a mut Option<(u16, u16)>,
@@ -130,24 +153,37 @@ impl Widget for TaskWidget<'_, '_> {
         let Some(id) = id else {
             return;
         };
-        let Some(v) = data.get_mut(id) else {
+        let Some(v) = data.get(id) else {
             return;
         };
 
+        let fields: Vec<String> = v
+            .extra_fields()
+            .map(|(key, node)| format!("{key}: {}", node_text(&node)))
+            .collect();
         let constraints = [
             Constraint::Max(3),
-            Constraint::Max(3),
+            Constraint::Max(tags::height(v)),
+            // Hidden without fields.
+            Constraint::Max(if fields.is_empty() {
+                0
+            } else {
+                fields.len() as u16 + 2
+            }),
             Constraint::Fill(1),
             Constraint::Fill(1),
         ];
         let layout = Layout::new(Direction::Vertical, constraints);
-        let [title_area, tags_area, context_area, boxes_area] = layout.areas(area);
+        let [title_area, tags_area, fields_area, context_area, boxes_area] = layout.areas(area);
 
-        let title_block = Block::bordered()
-            .title("Title")
-            .border_style(UNFOCUSED_BORDER);
-        Text::raw(v.title()).render(title_block.inner(title_area), buf);
-        title_block.render(title_area, buf);
+        TitleWidget {
+            tui: &mut tui.title,
+            focus: self.focus.and_then(|f| f.as_title()),
+            cursor_buf_pos,
+            task_id: id,
+            task: v,
+        }
+        .render(title_area, buf);
 
         TagsWidget {
             tui: &mut tui.tags,
@@ -158,6 +194,14 @@ impl Widget for TaskWidget<'_, '_> {
         }
         .render(tags_area, buf);
 
+        if !fields.is_empty() {
+            let fields_block = Block::bordered()
+                .title("Fields")
+                .border_style(Style::new().fg(UNFOCUSED_BORDER));
+            Text::from_iter(fields).render(fields_block.inner(fields_area), buf);
+            fields_block.render(fields_area, buf);
+        }
+
         let context_block =
             (Block::bordered().title("Context")).border_style(Style::new().fg(match focus {
                 Some(TaskFocus::Context(EditorFocus::Unlocked)) => FOCUSED_BORDER,
@@ -184,10 +228,29 @@ impl Widget for TaskWidget<'_, '_> {
             v.boxes()
                 .iter()
                 .map(|b| match b {
-                    BoxState::Checked(date_time) => {
-                        format!("Checked at {}\n", date_time.format("%Y-%m-%d %H:%M:%S"))
+                    BoxState::Checked {
+                        start: Some(start),
+                        end,
+                    } => format!(
+                        "Checked at {} ({} min)\n",
+                        end.format("%Y-%m-%d %H:%M:%S"),
+                        (*end - *start).num_minutes()
+                    ),
+                    BoxState::Checked { start: None, end } => {
+                        format!("Checked at {}\n", end.format("%Y-%m-%d %H:%M:%S"))
+                    }
+                    BoxState::Started(Some(start)) => {
+                        format!("Started at {}\n", start.format("%Y-%m-%d %H:%M:%S"))
                     }
-                    BoxState::Started => "Started\n".to_string(),
+                    BoxState::Started(None) => "Started\n".to_string(),
+                    BoxState::Voided { end, reason, .. } => format!(
+                        "Voided at {}{}\n",
+                        end.format("%Y-%m-%d %H:%M:%S"),
+                        reason
+                            .as_ref()
+                            .map(|r| format!(": {r}"))
+                            .unwrap_or_default()
+                    ),
                     BoxState::Empty => "Empty\n".to_string(),
                 })
                 .collect::<String>()
@@ -197,3 +260,19 @@ impl Widget for TaskWidget<'_, '_> {
         boxes_block.render(boxes_area, buf);
     }
 }
+
+/// A frontmatter value on one line.
+fn node_text(node: &Node) -> String {
+    match node {
+        Node::Null => String::new(),
+        Node::Scalar(scalar) => scalar.text.replace('\n', " "),
+        Node::Seq { items, .. } => items.iter().map(node_text).collect::<Vec<_>>().join(", "),
+        Node::Map { entries, .. } => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(key, node)| format!("{key}: {}", node_text(node)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
@@ -17,6 +17,8 @@ use crate::{
 
 pub struct EditorTui {
     view_offset: usize,
+    /// Chars of each line scrolled out to the left.
+    column_offset: usize,
 }
 
 pub enum Action {
@@ -25,7 +27,10 @@ pub enum Action {
 
 impl EditorTui {
     pub fn new() -> Self {
-        Self { view_offset: 0 }
+        Self {
+            view_offset: 0,
+            column_offset: 0,
+        }
     }
 
     pub fn handle_key_event(
@@ -79,6 +84,12 @@ impl EditorTui {
 
     pub fn set_text(&mut self, _text: &str) {
         self.view_offset = 0;
+        self.column_offset = 0;
+    }
+
+    /// As of the last render.
+    pub fn column_offset(&self) -> usize {
+        self.column_offset
     }
 }
 
@@ -116,9 +127,15 @@ impl Widget for EditorWidget<'_> {
         if cursor.line >= self.editor.view_offset + height {
             self.editor.view_offset += 1 + cursor.line - self.editor.view_offset - height;
         }
+        if cursor.column < self.editor.column_offset {
+            self.editor.column_offset = cursor.column;
+        }
+        if cursor.column >= self.editor.column_offset + width {
+            self.editor.column_offset = cursor.column + 1 - width;
+        }
         if let Some(EditorFocus::Locked) = self.focus {
             *self.cursor_buf_pos = Some((
-                (text_area.x as usize + cursor.column) as u16,
+                (text_area.x as usize + cursor.column - self.editor.column_offset) as u16,
                 (text_area.y as usize + cursor.line - self.editor.view_offset) as u16,
             ));
             if let Err(e) = ratatui::crossterm::execute!(io::stdout(), SetCursorStyle::SteadyBar) {
@@ -135,6 +152,11 @@ impl Widget for EditorWidget<'_> {
         for (y, l) in visible_lines.enumerate() {
             let rope_slice = l.to_string();
             let mut l = rope_slice.as_str();
+            let scrolled = l
+                .char_indices()
+                .nth(self.editor.column_offset)
+                .map_or(l.len(), |(i, _)| i);
+            l = &l[scrolled..];
             let mut x_offset = 0;
             let y = text_area.y + y as u16;
             while x_offset < width {
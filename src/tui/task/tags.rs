@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use chumsky::{Parser, text::Char};
 use ratatui::{
     crossterm::event::{KeyCode, KeyEvent},
-    style::Style,
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::Text,
     widgets::{Block, Widget},
 };
 
@@ -34,14 +36,19 @@ impl TagsTui {
         }
     }
 
+    /// Drops editors whose task may have changed underneath them.
+    pub fn clear(&mut self) {
+        self.task_to_editor.clear();
+    }
+
     pub fn handle_key(
         &mut self,
         key_event: KeyEvent,
         focus: &mut EditorFocus,
         data: &mut FilteredData,
-        task_id: Option<(usize, TaskID)>,
+        task_id: Option<TaskID>,
     ) -> Option<Action> {
-        let Some((visible_index, task_id)) = task_id else {
+        let Some(task_id) = task_id else {
             return Some(Action::Unhandled);
         };
         let Some(task) = data.get(task_id) else {
@@ -63,7 +70,7 @@ impl TagsTui {
                 *focus = EditorFocus::Unlocked;
                 let inner = textbox.inner().to_string();
                 match inline_tags().parse(&inner).into_result() {
-                    Ok(v) => data.set_tags(visible_index, v),
+                    Ok(v) => data.set_tags(task_id, v),
                     Err(e) => {
                         log::warn!("error parsing tags {e:?}")
                     }
@@ -108,9 +115,51 @@ fn derive_editable(task: &Task) -> (EditorTui, KeyboardEditable) {
     )
 }
 
+/// Nested tags are also shown as a tree, below the editor.
+pub fn height(task: &Task) -> u16 {
+    3 + tree(task).len() as u16
+}
+
+/// Lines of the tags, each level under its parent. Empty if no tag is nested.
+fn tree(task: &Task) -> Vec<String> {
+    #[derive(Default)]
+    struct Node<'a>(BTreeMap<&'a str, Node<'a>>);
+
+    fn lines(node: &Node, prefix: &str, out: &mut Vec<String>) {
+        for (i, (name, child)) in node.0.iter().enumerate() {
+            let last = i + 1 == node.0.len();
+            let (branch, indent) = if last {
+                ("└─ ", "   ")
+            } else {
+                ("├─ ", "│  ")
+            };
+            out.push(format!("{prefix}{branch}{name}"));
+            lines(child, &format!("{prefix}{indent}"), out);
+        }
+    }
+
+    if !task.tags().iter().any(|t| t.contains('/')) {
+        return vec![];
+    }
+    let mut root = Node::default();
+    for tag in task.tags() {
+        let mut node = &mut root;
+        for level in tag.split('/') {
+            node = node.0.entry(level).or_default();
+        }
+    }
+    let mut out = vec![];
+    // Top level tags start at the edge, without a branch.
+    for (name, child) in &root.0 {
+        out.push(name.to_string());
+        lines(child, "", &mut out);
+    }
+    out
+}
+
 pub struct TagsWidget<'a> {
     pub tui: &'a mut TagsTui,
-    pub task: &'a mut Task,
+    pub task: &'a Task,
     pub focus: Option<EditorFocus>,
     pub cursor_buf_pos: &'a mut Option<(u16, u16)>,
     pub task_id: TaskID,
@@ -127,6 +176,11 @@ impl<'a> Widget for TagsWidget<'a> {
         let outer_area = area;
         let area = filter_block.inner(area);
         filter_block.render(outer_area, buf);
+        let [area, tree_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(area);
+        Text::from_iter(tree(self.task))
+            .dark_gray()
+            .render(tree_area, buf);
 
         let (editor, text) = self
             .tui
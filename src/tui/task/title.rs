@@ -0,0 +1,137 @@
+use chumsky::text::Char;
+use ratatui::{
+    crossterm::event::{KeyCode, KeyEvent},
+    style::Style,
+    widgets::{Block, Widget},
+};
+
+use crate::{
+    filter::{FilteredData, TaskID},
+    storage::{Task, keyboard_edit::KeyboardEditable, text_edit::TextOp},
+    tui::{
+        FOCUSED_BORDER, LOCKED_EDITOR_BORDER, UNFOCUSED_BORDER,
+        task::editor::{EditorFocus, EditorTui, EditorWidget},
+    },
+};
+
+pub struct TitleTui {
+    /// Editor for the shown task's title, reset when editing ends.
+    editing: Option<(TaskID, EditorTui, KeyboardEditable)>,
+}
+
+pub enum Action {
+    Unhandled,
+}
+
+impl TitleTui {
+    pub fn new() -> Self {
+        Self { editing: None }
+    }
+
+    pub fn clear(&mut self) {
+        self.editing = None;
+    }
+
+    pub fn handle_key(
+        &mut self,
+        key_event: KeyEvent,
+        focus: &mut EditorFocus,
+        data: &mut FilteredData,
+        task_id: Option<TaskID>,
+    ) -> Option<Action> {
+        let Some(task_id) = task_id else {
+            return Some(Action::Unhandled);
+        };
+        let Some(task) = data.get(task_id) else {
+            return Some(Action::Unhandled);
+        };
+        if matches!(focus, EditorFocus::Unlocked) {
+            if let KeyCode::Enter = key_event.code {
+                *focus = EditorFocus::Locked;
+                return None;
+            }
+            return Some(Action::Unhandled);
+        }
+        let (_, _, textbox) = self.editing(task_id, task);
+        match key_event.code {
+            KeyCode::Enter => {
+                *focus = EditorFocus::Unlocked;
+                let title = textbox.inner().to_string().trim().to_string();
+                if title.is_empty() {
+                    log::warn!("ignoring empty title");
+                } else {
+                    data.set_title(task_id, title);
+                }
+                self.editing = None;
+            }
+            KeyCode::Esc => {
+                *focus = EditorFocus::Unlocked;
+                self.editing = None;
+            }
+            _ => {
+                let text_op = KeyboardEditable::map_key_event(key_event)?;
+                match text_op {
+                    TextOp::InsertText(ref cow) => {
+                        if !cow.contains(|c: char| c.is_newline()) {
+                            textbox.apply_text_op(text_op);
+                        }
+                    }
+                    _ => {
+                        textbox.apply_text_op(text_op);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn editing(
+        &mut self,
+        task_id: TaskID,
+        task: &Task,
+    ) -> &mut (TaskID, EditorTui, KeyboardEditable) {
+        if self
+            .editing
+            .as_ref()
+            .is_none_or(|(id, _, _)| *id != task_id)
+        {
+            self.editing = Some((
+                task_id,
+                EditorTui::new(),
+                KeyboardEditable::from_rope(task.title().into(), true),
+            ));
+        }
+        self.editing.as_mut().unwrap()
+    }
+}
+
+pub struct TitleWidget<'a> {
+    pub tui: &'a mut TitleTui,
+    pub task: &'a Task,
+    pub focus: Option<EditorFocus>,
+    pub cursor_buf_pos: &'a mut Option<(u16, u16)>,
+    pub task_id: TaskID,
+}
+
+impl<'a> Widget for TitleWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        let mut title_block = Block::bordered().title("Title");
+        title_block = title_block.border_style(Style::new().fg(match self.focus {
+            Some(EditorFocus::Unlocked) => FOCUSED_BORDER,
+            Some(EditorFocus::Locked) => LOCKED_EDITOR_BORDER,
+            _ => UNFOCUSED_BORDER,
+        }));
+        let outer_area = area;
+        let area = title_block.inner(area);
+        title_block.render(outer_area, buf);
+
+        let (_, editor, text) = self.tui.editing(self.task_id, self.task);
+        EditorWidget {
+            editor,
+            text,
+            cursor_buf_pos: self.cursor_buf_pos,
+            focus: self.focus,
+        }
+        .render(area, buf);
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::{Local, TimeDelta};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Widget};
+
+use crate::filter::{FilteredData, TaskID};
+use crate::storage::{BoxState, Date};
+use crate::tui::UNFOCUSED_BORDER;
+
+/// Counts down the running box and the break after it.
+pub struct TimerTui {
+    length: TimeDelta,
+    break_length: TimeDelta,
+    auto_check: bool,
+    phase: Phase,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    /// A box of the task is started and its pomodoro ends at `end`.
+    Work {
+        id: TaskID,
+        end: Date,
+    },
+    Break {
+        end: Date,
+    },
+}
+
+impl TimerTui {
+    pub fn new(length: TimeDelta, break_length: TimeDelta, auto_check: bool) -> Self {
+        Self {
+            length,
+            break_length,
+            auto_check,
+            phase: Phase::Idle,
+        }
+    }
+    pub fn is_running(&self) -> bool {
+        self.phase != Phase::Idle
+    }
+    pub fn start(&mut self, id: TaskID, start: Date) {
+        self.phase = Phase::Work {
+            id,
+            end: start + self.length,
+        };
+    }
+    pub fn start_break(&mut self, start: Date) {
+        self.phase = Phase::Break {
+            end: start + self.break_length,
+        };
+    }
+    pub fn tick(&mut self, data: &mut FilteredData, now: Date) {
+        match self.phase {
+            Phase::Work { id, end } => {
+                let running = data
+                    .get(id)
+                    .is_some_and(|t| t.boxes().iter().any(|b| matches!(b, BoxState::Started(_))));
+                if !running {
+                    // Voided, removed, or checked outside the timer.
+                    self.phase = Phase::Idle;
+                } else if self.auto_check && now >= end {
+                    data.check_started(id, end);
+                    self.start_break(end);
+                }
+            }
+            Phase::Break { end } if now >= end => self.phase = Phase::Idle,
+            Phase::Break { .. } | Phase::Idle => {}
+        }
+    }
+}
+
+pub struct TimerWidget<'a>(pub &'a TimerTui);
+
+impl Widget for TimerWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let now = Local::now().naive_local();
+        let (title, end) = match self.0.phase {
+            Phase::Idle => return,
+            Phase::Work { end, .. } => ("Pomodoro", end),
+            Phase::Break { end } => ("Break", end),
+        };
+        let remaining = (end - now).num_seconds();
+        let text = if remaining < 0 {
+            format!("+{:02}:{:02}", -remaining / 60, -remaining % 60)
+        } else {
+            format!("{:02}:{:02}", remaining / 60, remaining % 60)
+        };
+        let block = Block::bordered()
+            .title(title)
+            .border_style(Style::new().fg(UNFOCUSED_BORDER));
+        let text = Text::raw(text).centered();
+        if remaining < 0 {
+            text.red().render(block.inner(area), buf);
+        } else {
+            text.render(block.inner(area), buf);
+        }
+        block.render(area, buf);
+    }
+}